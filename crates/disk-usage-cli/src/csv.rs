@@ -0,0 +1,150 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::escape::escape_path;
+use crate::output::Output;
+use crate::scan::Group;
+use crate::{Config, OutputFormat};
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A column of --format csv and tsv. Sizes are in bytes.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum Column {
+    Path,
+    /// Number of components below the scanned root, which is at depth 0.
+    Depth,
+    IsFile,
+    /// The size that counts in the selected mode.
+    Size,
+    Total,
+    Ignored,
+    #[value(alias = "not-ignored")]
+    NotIgnored,
+    Tracked,
+    Untracked,
+    #[value(alias = "git")]
+    GitMetadata,
+    Count,
+    /// Apparent size, whether or not --apparent is given.
+    Apparent,
+    /// Disk usage, whether or not --apparent is given.
+    Disk,
+    /// Newest modification time, in seconds since the Unix epoch.
+    Mtime,
+}
+
+impl Column {
+    /// The columns printed without --columns.
+    pub const DEFAULT: [Column; 6] = [
+        Column::Path,
+        Column::Ignored,
+        Column::NotIgnored,
+        Column::GitMetadata,
+        Column::Total,
+        Column::Count,
+    ];
+
+    fn name(&self) -> String {
+        self.to_possible_value().unwrap().get_name().to_owned()
+    }
+
+    fn cell(&self, relative_path: &Path, group: &Group, root: &str, config: &Config) -> String {
+        let number = match self {
+            Column::Path => return path_cell(&Path::new(root).join(relative_path), config.format),
+            Column::IsFile => return group.is_file.to_string(),
+            Column::Depth => relative_path.components().count() as u64,
+            Column::Size => group.size(config.mode),
+            Column::Total => group.total(),
+            Column::Ignored => group.ignored,
+            Column::NotIgnored => group.not_ignored,
+            Column::Tracked => group.tracked,
+            Column::Untracked => group.untracked,
+            Column::GitMetadata => group.git_metadata,
+            Column::Count => group.count,
+            Column::Apparent => group.apparent_size,
+            Column::Disk => group.disk_size,
+            Column::Mtime => group.mtime,
+        };
+        number.to_string()
+    }
+}
+
+/// Write `entries` to `out` as comma or tab separated values, with a header row.
+pub fn write(entries: &[(PathBuf, Group)], root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let delimiter = if config.format == OutputFormat::Tsv { "\t" } else { "," };
+    let header: Vec<String> = config.columns.iter().map(Column::name).collect();
+    writeln!(out, "{}", header.join(delimiter))?;
+    for (path, group) in entries {
+        let cells: Vec<String> = config
+            .columns
+            .iter()
+            .map(|column| column.cell(path, group, root, config))
+            .collect();
+        writeln!(out, "{}", cells.join(delimiter))?;
+    }
+    Ok(())
+}
+
+/// The path as one cell, escaped like `escape_path` does. CSV quotes the cell like RFC 4180 when
+/// it contains a comma, a quote or a line break. TSV can't quote, so there a tab, carriage return
+/// or newline is escaped with a backslash too, like `\t`.
+fn path_cell(path: &Path, format: OutputFormat) -> String {
+    let cell = escape_path(path);
+    match format {
+        OutputFormat::Csv if cell.contains([',', '"', '\r', '\n']) => format!("\"{}\"", cell.replace('"', "\"\"")),
+        OutputFormat::Tsv => cell.replace('\t', "\\t").replace('\r', "\\r").replace('\n', "\\n"),
+        _ => cell,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::path_cell;
+    use crate::OutputFormat;
+    use std::path::Path;
+
+    #[test]
+    fn csv_quoting() {
+        let cell = |path: &str| path_cell(Path::new(path), OutputFormat::Csv);
+        assert_eq!(cell("src/main.rs"), "src/main.rs");
+        assert_eq!(cell("a,b"), "\"a,b\"");
+        assert_eq!(cell("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(cell("two\nlines"), "\"two\nlines\"");
+        assert_eq!(cell("tab\there"), "tab\there");
+        assert_eq!(cell("back\\slash"), "back\\\\slash");
+        assert_eq!(cell("literal\\xFF"), "literal\\\\xFF");
+    }
+
+    #[test]
+    fn tsv_escaping() {
+        let cell = |path: &str| path_cell(Path::new(path), OutputFormat::Tsv);
+        assert_eq!(cell("a,b \"c\""), "a,b \"c\"");
+        assert_eq!(cell("tab\there"), "tab\\there");
+        assert_eq!(cell("cr\r\nlf"), "cr\\r\\nlf");
+        assert_eq!(cell("back\\slash"), "back\\\\slash");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9,\xff\xfe.txt"));
+        assert_eq!(path_cell(path, OutputFormat::Csv), "\"caf\\xE9,\\xFF\\xFE.txt\"");
+        assert_eq!(path_cell(path, OutputFormat::Tsv), "caf\\xE9,\\xFF\\xFE.txt");
+    }
+}
@@ -0,0 +1,373 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::{self, Output};
+use crate::scan::Group;
+use crate::snapshot::{self, Entry, Options, Snapshot};
+use crate::table::{align_columns, format_delta, format_size};
+use crate::{compare_components, visualize, Mode};
+use clap::{Arg, ArgAction, ArgMatches};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub fn command(args: impl IntoIterator<Item = Arg>) -> clap::Command {
+    clap::Command::new("diff")
+        .about("Show what grew and what shrank between two snapshots saved with --save")
+        .long_about("Show what grew and what shrank between two snapshots saved with --save. Lists every directory whose size changed, biggest change first, with how the ignored and not ignored bytes changed and whether the path is new or deleted. With -a files are listed too. With --web the treemap shows the whole newer snapshot, unchanged paths too, colored by how much each path grew or shrank. Deleted paths aren't in it, they only show in how much their parents shrank. Snapshots saved with different --max-depth are compared down to the shallower one.")
+        .disable_help_flag(true)
+        .args(args)
+        .arg(
+            Arg::new("OLD")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("The snapshot to compare against"),
+        )
+        .arg(
+            Arg::new("NEW")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("The snapshot to compare"),
+        )
+}
+
+/// A path found in either snapshot.
+struct Change {
+    path: PathBuf,
+    old: Option<Group>,
+    new: Option<Group>,
+}
+
+impl Change {
+    fn old_group(&self) -> Group {
+        self.old.unwrap_or_default()
+    }
+
+    fn new_group(&self) -> Group {
+        self.new.unwrap_or_default()
+    }
+
+    fn is_file(&self) -> bool {
+        self.new.or(self.old).is_some_and(|group| group.is_file)
+    }
+
+    fn delta(&self, mode: Mode) -> i64 {
+        self.new_group().size(mode) as i64 - self.old_group().size(mode) as i64
+    }
+
+    fn ignored_delta(&self) -> i64 {
+        self.new_group().ignored as i64 - self.old_group().ignored as i64
+    }
+
+    fn not_ignored_delta(&self) -> i64 {
+        self.new_group().not_ignored as i64 - self.old_group().not_ignored as i64
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.old, &self.new) {
+            (None, Some(_)) => "new",
+            (Some(_), None) => "deleted",
+            _ => "",
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) {
+    let old_file = matches.get_one::<PathBuf>("OLD").unwrap();
+    let new_file = matches.get_one::<PathBuf>("NEW").unwrap();
+    let old = snapshot::load(old_file);
+    let new = snapshot::load(new_file);
+    let mode = new.mode().unwrap();
+    if old.mode() != Some(mode) {
+        eprintln!(
+            "{}: saved with --mode {}, but {} with --mode {}",
+            old_file.display(),
+            old.mode().unwrap().name(),
+            new_file.display(),
+            mode.name()
+        );
+        std::process::exit(1);
+    }
+    if old.options.apparent != new.options.apparent || old.options.direct_size != new.options.direct_size {
+        eprintln!(
+            "{} and {}: saved with different --apparent or --direct-size, so their sizes can't be compared",
+            old_file.display(),
+            new_file.display()
+        );
+        std::process::exit(1);
+    }
+    let differing = differing_options(&old.options, &new.options);
+    if !differing.is_empty() {
+        eprintln!(
+            "Note: {} and {} were saved with different {}, part of the change comes from that",
+            old_file.display(),
+            new_file.display(),
+            differing.join(", ")
+        );
+    }
+    // Deeper paths are only in one of the snapshots, they would all look new or deleted.
+    let depth = old.options.depth.into_iter().chain(new.options.depth).min();
+    if old.options.depth != new.options.depth {
+        eprintln!("Note: only comparing down to --max-depth {}, as deep as both snapshots go", depth.unwrap());
+    }
+    if old.absolute_root != new.absolute_root {
+        eprintln!(
+            "Note: comparing snapshots of {} and {}",
+            describe_root(&old),
+            describe_root(&new)
+        );
+    }
+    if new.timestamp < old.timestamp {
+        eprintln!("Note: {} is older than {}, growth shows as shrinkage", new_file.display(), old_file.display());
+    }
+    eprintln!(
+        "Changes in {} over {} - showing {}",
+        describe_root(&new),
+        format_elapsed(new.timestamp.abs_diff(old.timestamp)),
+        mode.description()
+    );
+
+    let max_depth = matches.get_one::<u64>("max-depth").copied().unwrap_or(u64::MAX);
+    let mut changes: Vec<Change> = changes(old.entries, new.entries, depth)
+        .into_iter()
+        .filter(|change| change.path.components().count() as u64 <= max_depth)
+        .collect();
+
+    if matches.get_flag("web") || matches.get_flag("open") {
+        let deltas: HashMap<PathBuf, i64> = changes
+            .iter()
+            .map(|change| (change.path.clone(), change.delta(mode)))
+            .collect();
+        // The whole newer tree, not only what changed. Deleted paths would take up no room, and
+        // the sizes of their parents, and so their deltas, include them already.
+        let mut pairs: Vec<(PathBuf, Group)> = changes
+            .into_iter()
+            .filter_map(|change| Some((change.path, change.new?)))
+            .collect();
+        pairs.sort_by(|a, b| compare_components(&a.0, &b.0, Ordering::Greater));
+        visualize::view_in_browser(&pairs, None, Some(&deltas), matches.get_flag("open"));
+        return;
+    }
+
+    let show_files = matches.get_flag("show-files");
+    changes.retain(|change| {
+        (show_files || !change.is_file())
+            && (change.old.is_none()
+                || change.new.is_none()
+                || change.delta(mode) != 0
+                || change.ignored_delta() != 0
+                || change.not_ignored_delta() != 0)
+    });
+    changes.sort_by(|a, b| {
+        b.delta(mode)
+            .unsigned_abs()
+            .cmp(&a.delta(mode).unsigned_abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let human_readable = matches.get_flag("human-readable");
+    let mut out = Output::new(matches.get_one::<PathBuf>("output").map(PathBuf::as_path));
+    if let Err(err) = print_changes(&changes, &new.root, mode, human_readable, &mut out).and_then(|()| out.flush()) {
+        output::exit_on_error(err);
+    }
+}
+
+/// Every path of both snapshots, with what each of them had there.
+fn changes(old: Vec<Entry>, new: Vec<Entry>, depth: Option<u64>) -> Vec<Change> {
+    let within = |entry: &Entry| match depth {
+        Some(depth) => entry.path.components().count() as u64 <= depth,
+        None => true,
+    };
+    let mut changes: HashMap<PathBuf, Change> = HashMap::new();
+    for Entry { path, group } in old.into_iter().filter(within) {
+        changes.insert(
+            path.clone(),
+            Change {
+                path,
+                old: Some(group),
+                new: None,
+            },
+        );
+    }
+    for Entry { path, group } in new.into_iter().filter(within) {
+        changes
+            .entry(path.clone())
+            .or_insert(Change {
+                path,
+                old: None,
+                new: None,
+            })
+            .new = Some(group);
+    }
+    changes.into_values().collect()
+}
+
+fn print_changes(changes: &[Change], root: &str, mode: Mode, human_readable: bool, out: &mut Output) -> io::Result<()> {
+    let header = ["change", "old", "new", "ignored", "not-ignored"].map(String::from);
+    let rows: Vec<[String; 5]> = std::iter::once(header)
+        .chain(changes.iter().map(|change| {
+            let (old, new) = (change.old_group(), change.new_group());
+            [
+                format_delta(old.size(mode), new.size(mode), human_readable),
+                format_size(change.old.map(|group| group.size(mode)), human_readable),
+                format_size(change.new.map(|group| group.size(mode)), human_readable),
+                format_delta(old.ignored, new.ignored, human_readable),
+                format_delta(old.not_ignored, new.not_ignored, human_readable),
+            ]
+        }))
+        .collect();
+
+    let lines = align_columns(&rows);
+    writeln!(out, "{} {:7} path", lines[0], "")?;
+    for (change, line) in changes.iter().zip(&lines[1..]) {
+        writeln!(
+            out,
+            "{} {:7} {}",
+            line,
+            change.status(),
+            Path::new(root).join(&change.path).display()
+        )?;
+    }
+    Ok(())
+}
+
+/// The options, other than --apparent and --direct-size, that change which bytes a snapshot
+/// counts and that differ between `old` and `new`.
+fn differing_options(old: &Options, new: &Options) -> Vec<&'static str> {
+    [
+        ("--hidden", old.hidden != new.hidden),
+        ("--count-links", old.count_links != new.count_links),
+        ("--one-file-system", old.one_file_system != new.one_file_system),
+        ("--exclude-mode", old.omit_excluded != new.omit_excluded),
+    ]
+    .into_iter()
+    .filter_map(|(option, differs)| differs.then_some(option))
+    .collect()
+}
+
+fn describe_root(snapshot: &Snapshot) -> &str {
+    snapshot.absolute_root.as_deref().unwrap_or(&snapshot.root)
+}
+
+/// The time between two snapshots, in the biggest unit that fits.
+fn format_elapsed(seconds: u64) -> String {
+    let units = [(86400, "day"), (3600, "hour"), (60, "minute")];
+    let (length, unit) = units
+        .into_iter()
+        .find(|(length, _)| seconds >= *length)
+        .unwrap_or((1, "second"));
+    let count = seconds / length;
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changes, differing_options};
+    use crate::scan::Group;
+    use crate::snapshot::{Entry, Options};
+    use crate::Mode;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, ignored: u64, not_ignored: u64) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            group: Group {
+                ignored,
+                not_ignored,
+                ..Group::default()
+            },
+        }
+    }
+
+    #[test]
+    fn changes_of_both_snapshots() {
+        let old = vec![
+            entry("", 1000, 5000),
+            entry("same", 0, 2000),
+            entry("grew", 1000, 0),
+            entry("deleted", 0, 3000),
+        ];
+        let new = vec![
+            entry("", 4000, 3000),
+            entry("same", 0, 2000),
+            entry("grew", 3000, 0),
+            entry("new", 1000, 1000),
+        ];
+        let mut changes = changes(old, new, None);
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        let summary: Vec<(&str, &str, i64, i64, i64)> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.path.to_str().unwrap(),
+                    change.status(),
+                    change.delta(Mode::Du),
+                    change.ignored_delta(),
+                    change.not_ignored_delta(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("", "", 1000, 3000, -2000),
+                ("deleted", "deleted", -3000, 0, -3000),
+                ("grew", "", 2000, 2000, 0),
+                ("new", "new", 2000, 1000, 1000),
+                ("same", "", 0, 0, 0),
+            ]
+        );
+        assert_eq!(changes[0].delta(Mode::NotIgnored), -2000);
+    }
+
+    #[test]
+    fn changes_down_to_the_shallower_snapshot() {
+        let old = vec![entry("", 0, 100), entry("src", 0, 100)];
+        let new = vec![
+            entry("", 0, 150),
+            entry("src", 0, 150),
+            entry("src/lib", 0, 50),
+            entry("src/lib/deep", 0, 50),
+        ];
+        let mut changes = changes(old, new, Some(1));
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        let summary: Vec<(&str, &str, i64)> = changes
+            .iter()
+            .map(|change| (change.path.to_str().unwrap(), change.status(), change.delta(Mode::Du)))
+            .collect();
+        assert_eq!(summary, [("", "", 50), ("src", "", 50)]);
+    }
+
+    #[test]
+    fn options_that_change_what_is_counted() {
+        let options = |hidden: bool, depth: Option<u64>, one_file_system: bool| Options {
+            apparent: false,
+            hidden,
+            depth,
+            direct_size: false,
+            git_status: false,
+            count_links: false,
+            one_file_system,
+            omit_excluded: false,
+        };
+        assert!(differing_options(&options(false, Some(1), false), &options(false, None, false)).is_empty());
+        assert_eq!(
+            differing_options(&options(true, None, false), &options(false, None, true)),
+            ["--hidden", "--one-file-system"]
+        );
+    }
+}
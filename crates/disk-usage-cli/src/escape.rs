@@ -0,0 +1,127 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// A path as text that can be read back with `unescape_path`, byte for byte. Bytes that aren't
+/// valid UTF-8 are written as `\xNN`, and a backslash as `\\`.
+pub fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    let push_text = |escaped: &mut String, text: &str| {
+        for c in text.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                c => escaped.push(c),
+            }
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let mut bytes = path.as_os_str().as_bytes();
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(text) => {
+                    push_text(&mut escaped, text);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = bytes.split_at(err.valid_up_to());
+                    push_text(&mut escaped, std::str::from_utf8(valid).unwrap());
+                    let invalid = err.error_len().unwrap_or(rest.len());
+                    for byte in &rest[..invalid] {
+                        write!(escaped, "\\x{:02X}", byte).unwrap();
+                    }
+                    bytes = &rest[invalid..];
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    push_text(&mut escaped, &path.to_string_lossy());
+
+    escaped
+}
+
+/// The path `escape_path` made `escaped` from. None when a backslash isn't followed by
+/// another backslash or by `x` and two hex digits.
+pub fn unescape_path(escaped: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [b'\\', after @ ..] => {
+                bytes.push(b'\\');
+                rest = after;
+            }
+            [b'x', high, low, after @ ..] => {
+                let digit = |byte: u8| (byte as char).to_digit(16);
+                bytes.push((digit(*high)? * 16 + digit(*low)?) as u8);
+                rest = after;
+            }
+            _ => return None,
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(not(unix))]
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_path, unescape_path};
+    use std::path::Path;
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(escape_path(Path::new("a\\b")), "a\\\\b");
+        assert_eq!(escape_path(Path::new("literal\\xFF")), "literal\\\\xFF");
+        assert_eq!(unescape_path("a\\\\b").unwrap(), Path::new("a\\b"));
+        assert_eq!(unescape_path("literal\\\\xFF").unwrap(), Path::new("literal\\xFF"));
+    }
+
+    #[test]
+    fn not_escaped() {
+        assert_eq!(unescape_path("a\\b"), None);
+        assert_eq!(unescape_path("trailing\\"), None);
+        assert_eq!(unescape_path("short\\xF"), None);
+        assert_eq!(unescape_path("not hex\\xGG"), None);
+        assert_eq!(unescape_path("sign\\x+F"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_round_trip() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        for bytes in [&b"caf\xe9"[..], b"\xff\xfe/\\x41\\", b"ok/\xc3\xa9t\xc3", b"tab\tnew\nline"] {
+            let path = Path::new(OsStr::from_bytes(bytes));
+            let escaped = escape_path(path);
+            assert_eq!(unescape_path(&escaped).unwrap(), path, "{}", escaped);
+        }
+        assert_eq!(escape_path(Path::new(OsStr::from_bytes(b"caf\xe9"))), "caf\\xE9");
+    }
+}
@@ -0,0 +1,149 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::Output;
+use crate::scan::Group;
+use crate::Config;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Write `entries` to `out` as folded stacks, one `root;dir;file bytes` line per entry, for
+/// flamegraph.pl or inferno.
+///
+/// Every line holds only the bytes of the entry itself, the bytes below it are on the lines of
+/// its children. With --folded-prefix the bytes of each entry are split into ignored, tracked,
+/// untracked, not-ignored (outside of any repository) and git, and each part gets a line of its
+/// own, starting with that name.
+pub fn write(entries: &[(PathBuf, Group)], root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut own: Vec<Group> = entries.iter().map(|(_, group)| *group).collect();
+    if config.use_recursive_size {
+        let index: HashMap<&Path, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| (path.as_path(), i))
+            .collect();
+        for (path, group) in entries {
+            if let Some(&parent) = path.parent().and_then(|parent| index.get(parent)) {
+                let parent = &mut own[parent];
+                parent.not_ignored = parent.not_ignored.saturating_sub(group.not_ignored);
+                parent.ignored = parent.ignored.saturating_sub(group.ignored);
+                parent.git_metadata = parent.git_metadata.saturating_sub(group.git_metadata);
+                parent.tracked = parent.tracked.saturating_sub(group.tracked);
+                parent.untracked = parent.untracked.saturating_sub(group.untracked);
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by(|&a, &b| entries[a].0.cmp(&entries[b].0));
+    for i in order {
+        let stack = frames(root, &entries[i].0);
+        let group = &own[i];
+        if !config.folded_prefix {
+            let size = group.size(config.mode);
+            if size > 0 {
+                writeln!(out, "{} {}", stack, size)?;
+            }
+            continue;
+        }
+        let parts = [
+            ("ignored", group.ignored),
+            ("tracked", group.tracked),
+            ("untracked", group.untracked),
+            // Subtracting the sizes of the children can leave the parts of an entry's own bytes
+            // inconsistent, like more tracked than not ignored bytes.
+            (
+                "not-ignored",
+                group.not_ignored.saturating_sub(group.tracked).saturating_sub(group.untracked),
+            ),
+            ("git", group.git_metadata),
+        ];
+        for (prefix, size) in parts {
+            if size > 0 {
+                writeln!(out, "{};{} {}", prefix, stack, size)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The frames of the stack of `relative_path`, separated by `;`. A `;` or line break in a
+/// name would split it, so they become `_`.
+fn frames(root: &str, relative_path: &Path) -> String {
+    let frame = |name: &str| name.replace([';', '\n', '\r'], "_");
+    let mut stack = frame(root);
+    for component in relative_path.iter() {
+        stack.push(';');
+        stack.push_str(&frame(&component.to_string_lossy()));
+    }
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frames, write};
+    use crate::output::Output;
+    use crate::scan::Group;
+    use crate::tests::TempDir;
+    use crate::{command, Config};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn separators_in_names() {
+        assert_eq!(frames("root", Path::new("")), "root");
+        assert_eq!(frames("./a;b", Path::new("c\nd/e\r;f")), "./a_b;c_d;e__f");
+    }
+
+    fn folded(args: &[&str], entries: &[(PathBuf, Group)]) -> String {
+        let config = Config::parse(command().get_matches_from([&["duh", "--format", "folded"], args].concat()));
+        let td = TempDir::new();
+        let file = td.path().join("folded");
+        let mut out = Output::new(Some(&file));
+        write(entries, "root", &config, &mut out).unwrap();
+        out.flush().unwrap();
+        std::fs::read_to_string(file).unwrap()
+    }
+
+    fn group(not_ignored: u64, tracked: u64, ignored: u64, is_file: bool) -> Group {
+        Group {
+            not_ignored,
+            tracked,
+            ignored,
+            is_file,
+            ..Group::default()
+        }
+    }
+
+    #[test]
+    fn own_bytes() {
+        let entries = [
+            (PathBuf::from("src;1/main.rs"), group(300, 300, 0, true)),
+            (PathBuf::from("src;1"), group(400, 300, 50, false)),
+            (PathBuf::from(""), group(1000, 300, 50, false)),
+        ];
+        assert_eq!(folded(&[], &entries), "root 600\nroot;src_1 150\nroot;src_1;main.rs 300\n");
+        assert_eq!(
+            folded(&["--folded-prefix"], &entries),
+            "not-ignored;root 600\nignored;root;src_1 50\nnot-ignored;root;src_1 100\ntracked;root;src_1;main.rs 300\n"
+        );
+    }
+
+    #[test]
+    fn more_tracked_than_not_ignored() {
+        let entries = [(PathBuf::from(""), group(100, 150, 0, false))];
+        assert_eq!(folded(&["--folded-prefix"], &entries), "tracked;root 150\n");
+    }
+}
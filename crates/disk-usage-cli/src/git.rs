@@ -0,0 +1,515 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::scan::Status;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Finds the git work tree that every scanned path belongs to, and optionally tells which files
+/// are tracked by reading the index of each repository. Git itself is never run.
+pub struct Repositories {
+    /// Canonical path of the root being scanned.
+    root: PathBuf,
+    read_index: bool,
+    /// The repository that the contents of each directory belong to, keyed by the directory
+    /// path relative to the root.
+    dirs: RwLock<HashMap<PathBuf, Option<Arc<Repository>>>>,
+}
+
+impl Repositories {
+    pub fn new(root: &str, read_index: bool) -> io::Result<Self> {
+        Ok(Self {
+            root: Path::new(root).canonicalize()?,
+            read_index,
+            dirs: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Status of a not-ignored entry, given its path relative to the root. Only meaningful when
+    /// the index was read.
+    pub fn status(&self, relative_path: &Path, is_dir: bool) -> Status {
+        // A directory with a .git inside is tracked (as a submodule) by the repository of its
+        // parent directory, while its contents belong to its own repository.
+        // The root itself has no parent, and is never tracked.
+        let repository = match relative_path.parent().and_then(|parent| self.repository(parent)) {
+            Some(repository) => repository,
+            None => return Status::NotIgnored,
+        };
+        let index = match &repository.index {
+            Some(index) => index,
+            None => return Status::NotIgnored,
+        };
+        let path = self.root.join(relative_path);
+        let path = match path.strip_prefix(&repository.work_tree) {
+            Ok(path) => path,
+            Err(_) => return Status::NotIgnored,
+        };
+        if index.files.contains(path) || (is_dir && index.dirs.contains(path)) {
+            Status::Tracked
+        } else {
+            Status::Untracked
+        }
+    }
+
+    /// The repository whose work tree holds the contents of `dir`, a path relative to the root.
+    pub fn repository(&self, dir: &Path) -> Option<Arc<Repository>> {
+        if let Some(repository) = self.dirs.read().unwrap().get(dir) {
+            return repository.clone();
+        }
+        let absolute = self.root.join(dir);
+        let repository = if absolute.join(".git").exists() {
+            Repository::open(&absolute, self.read_index).map(Arc::new)
+        } else if dir.as_os_str().is_empty() {
+            // The root might be somewhere inside a work tree.
+            absolute
+                .ancestors()
+                .skip(1)
+                .find(|ancestor| ancestor.join(".git").exists())
+                .and_then(|work_tree| Repository::open(work_tree, self.read_index))
+                .map(Arc::new)
+        } else {
+            self.repository(dir.parent().unwrap_or(Path::new("")))
+        };
+        self.dirs.write().unwrap().insert(dir.to_path_buf(), repository.clone());
+        repository
+    }
+}
+
+/// How a work tree is attached to its git directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// `.git` is the git directory.
+    Repository,
+    /// `.git` is a file pointing into the `worktrees` directory of another repository.
+    Worktree,
+    /// `.git` is a file pointing into the `modules` directory of the superproject.
+    Submodule,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Repository => "repository",
+            Kind::Worktree => "worktree",
+            Kind::Submodule => "submodule",
+        }
+    }
+}
+
+/// One git work tree.
+pub struct Repository {
+    /// Canonical path of the work tree.
+    pub work_tree: PathBuf,
+    pub kind: Kind,
+    /// None when we were not asked to read the index, or could not make sense of it.
+    index: Option<Index>,
+}
+
+/// The paths in the index of one work tree.
+struct Index {
+    files: HashSet<PathBuf>,
+    /// Every directory that contains a tracked file, however deep.
+    dirs: HashSet<PathBuf>,
+}
+
+impl Repository {
+    /// Spell the work tree relative to `root`, the path given on the command line, when it is
+    /// inside it.
+    pub fn display_path(&self, root: &str) -> PathBuf {
+        let relative = Path::new(root)
+            .canonicalize()
+            .ok()
+            .and_then(|absolute_root| self.work_tree.strip_prefix(absolute_root).ok().map(Path::to_path_buf));
+        match relative {
+            Some(relative) => Path::new(root).join(relative),
+            None => self.work_tree.clone(),
+        }
+    }
+
+    /// Returns None when `.git` is neither a git directory nor a file pointing to one.
+    fn open(work_tree: &Path, read_index: bool) -> Option<Repository> {
+        // The same parsing of `.git` files the walker uses to find `info/exclude`.
+        let git_type = std::fs::metadata(work_tree.join(".git")).ok()?.file_type();
+        let git_dir = ignore::resolve_git_dir(work_tree, Some(git_type)).ok()?;
+        let common_dir = ignore::resolve_git_commondir(work_tree, Some(git_type));
+        let kind = if git_type.is_dir() {
+            Kind::Repository
+        } else if common_dir.is_ok() {
+            Kind::Worktree
+        } else {
+            Kind::Submodule
+        };
+        let common_dir = common_dir.unwrap_or_else(|_| git_dir.clone());
+        Some(Repository {
+            work_tree: work_tree.to_path_buf(),
+            kind,
+            index: if read_index { Index::read(&git_dir, &common_dir) } else { None },
+        })
+    }
+}
+
+impl Index {
+    /// Returns None when the index is in a format we don't understand. A repository without an
+    /// index, e.g. one without any commit, has an empty index.
+    fn read(git_dir: &Path, common_dir: &Path) -> Option<Index> {
+        let mut paths = Vec::new();
+        match std::fs::read(git_dir.join("index")) {
+            Ok(index) => {
+                let hash_len = hash_len(common_dir);
+                let shared = parse_index(&index, hash_len, &mut paths)?;
+                // With a split index most entries live in a shared index. We take the union of
+                // both, which only errs on the side of calling a deleted file tracked.
+                if let Some(shared) = shared {
+                    let shared = std::fs::read(git_dir.join(format!("sharedindex.{}", shared))).ok()?;
+                    parse_index(&shared, hash_len, &mut paths)?;
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(_) => return None,
+        }
+
+        let mut files = HashSet::with_capacity(paths.len());
+        let mut dirs = HashSet::new();
+        for path in paths {
+            let path = path_from_index(&path);
+            let mut dir = path.parent();
+            while let Some(parent) = dir.filter(|parent| !parent.as_os_str().is_empty()) {
+                if !dirs.insert(parent.to_path_buf()) {
+                    break;
+                }
+                dir = parent.parent();
+            }
+            files.insert(path);
+        }
+        Some(Index { files, dirs })
+    }
+}
+
+/// SHA-256 repositories use longer object ids in the index. The config of a linked worktree
+/// lives in the common directory.
+fn hash_len(common_dir: &Path) -> usize {
+    let config = std::fs::read_to_string(common_dir.join("config")).unwrap_or_default();
+    let sha256 = config.lines().any(|line| {
+        let line = line.trim().to_ascii_lowercase();
+        let mut parts = line.splitn(2, '=');
+        parts.next().map(str::trim) == Some("objectformat") && parts.next().map(str::trim) == Some("sha256")
+    });
+    if sha256 {
+        32
+    } else {
+        20
+    }
+}
+
+/// Append the path of every entry in a git index file to `paths`.
+///
+/// Returns the hex id of the shared index when this is a split index. Returns None if the file
+/// is not an index we understand. The format is described in git's
+/// Documentation/gitformat-index.txt.
+fn parse_index(data: &[u8], hash_len: usize, paths: &mut Vec<Vec<u8>>) -> Option<Option<String>> {
+    if data.get(0..4)? != b"DIRC" {
+        return None;
+    }
+    let version = read_u32(data, 4)?;
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let count = read_u32(data, 8)?;
+
+    let mut offset = 12;
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = offset;
+        // ctime, mtime, dev, ino, mode, uid, gid, size and the object id.
+        offset += 40 + hash_len;
+        let flags = read_u16(data, offset)?;
+        offset += 2;
+        if version >= 3 && flags & 0x4000 != 0 {
+            offset += 2;
+        }
+        let path = if version == 4 {
+            // The path is stored as the number of bytes to drop from the end of the previous
+            // path, followed by the suffix to append.
+            let (strip, len) = read_varint(data.get(offset..)?)?;
+            offset += len;
+            let end = offset + data.get(offset..)?.iter().position(|&b| b == 0)?;
+            let keep = previous.len().checked_sub(strip as usize)?;
+            let mut path = previous[..keep].to_vec();
+            path.extend_from_slice(&data[offset..end]);
+            offset = end + 1;
+            path
+        } else {
+            let end = offset + data.get(offset..)?.iter().position(|&b| b == 0)?;
+            let path = data[offset..end].to_vec();
+            // Entries are padded with 1-8 NUL bytes to a multiple of eight bytes.
+            offset = start + (end - start + 8) / 8 * 8;
+            path
+        };
+        if offset > data.len() {
+            return None;
+        }
+        previous.clone_from(&path);
+        paths.push(path);
+    }
+
+    // Extensions follow the entries, and the file ends with a checksum.
+    let mut shared = None;
+    while offset + 8 + hash_len <= data.len() {
+        let signature = &data[offset..offset + 4];
+        let size = read_u32(data, offset + 4)? as usize;
+        if signature == b"link" {
+            let id = data.get(offset + 8..offset + 8 + hash_len)?;
+            if id.iter().any(|&b| b != 0) {
+                shared = Some(id.iter().map(|b| format!("{:02x}", b)).collect());
+            }
+        }
+        offset += 8 + size;
+    }
+    Some(shared)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+/// The variable width integer used by index version 4. Returns the value and the number of
+/// bytes read, or None when it doesn't fit in 64 bits.
+fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = (*data.first()? & 0x7f) as u64;
+    let mut len = 1;
+    let mut byte = data[0];
+    while byte & 0x80 != 0 {
+        byte = *data.get(len)?;
+        value = value.checked_add(1)?.checked_mul(0x80)? | (byte & 0x7f) as u64;
+        len += 1;
+    }
+    Some((value, len))
+}
+
+#[cfg(unix)]
+fn path_from_index(path: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(path))
+}
+
+#[cfg(not(unix))]
+fn path_from_index(path: &[u8]) -> PathBuf {
+    // Paths in the index always use `/`, and are UTF-8 on Windows.
+    String::from_utf8_lossy(path).split('/').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_len, parse_index, read_varint, Kind, Repositories};
+    use crate::scan::Status;
+    use crate::tests::TempDir;
+    use std::path::Path;
+
+    fn header(version: u32, count: u32) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        data
+    }
+
+    /// Everything of an entry before its path: zeroed stat data and object id, then the flags.
+    fn stat(data: &mut Vec<u8>, hash_len: usize, flags: u16) {
+        data.resize(data.len() + 40 + hash_len, 0);
+        data.extend_from_slice(&flags.to_be_bytes());
+    }
+
+    /// An entry of index version 2 or 3, padded with NULs to a multiple of eight bytes.
+    fn entry(data: &mut Vec<u8>, hash_len: usize, path: &str, extended: bool) {
+        let start = data.len();
+        if extended {
+            stat(data, hash_len, 0x4000 | path.len() as u16);
+            data.extend_from_slice(&[0x20, 0]);
+        } else {
+            stat(data, hash_len, path.len() as u16);
+        }
+        data.extend_from_slice(path.as_bytes());
+        let len = data.len() - start;
+        data.resize(start + (len + 8) / 8 * 8, 0);
+    }
+
+    /// An entry of index version 4: drop `strip` bytes from the previous path, then add `suffix`.
+    fn entry_v4(data: &mut Vec<u8>, hash_len: usize, strip: u8, suffix: &str) {
+        stat(data, hash_len, 0);
+        data.push(strip);
+        data.extend_from_slice(suffix.as_bytes());
+        data.push(0);
+    }
+
+    fn checksum(data: &mut Vec<u8>, hash_len: usize) {
+        data.resize(data.len() + hash_len, 0xab);
+    }
+
+    fn parse(data: &[u8], hash_len: usize) -> Option<(Vec<String>, Option<String>)> {
+        let mut paths = Vec::new();
+        let shared = parse_index(data, hash_len, &mut paths)?;
+        let paths = paths.into_iter().map(|path| String::from_utf8(path).unwrap()).collect();
+        Some((paths, shared))
+    }
+
+    fn index_v2() -> Vec<u8> {
+        let mut data = header(2, 3);
+        entry(&mut data, 20, "README.md", false);
+        entry(&mut data, 20, "src/main.rs", false);
+        // Exactly eight bytes of stat, id and flags plus path before padding, so a full block of
+        // NULs follows.
+        entry(&mut data, 20, "src/lib.rs", false);
+        checksum(&mut data, 20);
+        data
+    }
+
+    #[test]
+    fn version_2() {
+        let (paths, shared) = parse(&index_v2(), 20).unwrap();
+        assert_eq!(paths, ["README.md", "src/main.rs", "src/lib.rs"]);
+        assert_eq!(shared, None);
+    }
+
+    #[test]
+    fn version_3_with_extended_flags() {
+        let mut data = header(3, 2);
+        entry(&mut data, 20, "intent-to-add.txt", true);
+        entry(&mut data, 20, "tracked.txt", false);
+        checksum(&mut data, 20);
+        let (paths, _) = parse(&data, 20).unwrap();
+        assert_eq!(paths, ["intent-to-add.txt", "tracked.txt"]);
+    }
+
+    #[test]
+    fn version_4_path_compression() {
+        let mut data = header(4, 3);
+        entry_v4(&mut data, 20, 0, "src/lib.rs");
+        entry_v4(&mut data, 20, 6, "main.rs");
+        entry_v4(&mut data, 20, 11, "tests/a.rs");
+        checksum(&mut data, 20);
+        let (paths, _) = parse(&data, 20).unwrap();
+        assert_eq!(paths, ["src/lib.rs", "src/main.rs", "tests/a.rs"]);
+    }
+
+    #[test]
+    fn version_4_strips_more_than_the_previous_path() {
+        let mut data = header(4, 2);
+        entry_v4(&mut data, 20, 0, "a");
+        entry_v4(&mut data, 20, 5, "b");
+        checksum(&mut data, 20);
+        assert!(parse(&data, 20).is_none());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = index_v2();
+        let entries_end = data.len() - 20;
+        for len in 0..entries_end {
+            assert!(parse(&data[..len], 20).is_none(), "truncated to {} bytes", len);
+        }
+        // Without the checksum, there are no extensions to read.
+        assert_eq!(parse(&data[..entries_end], 20).unwrap().0.len(), 3);
+    }
+
+    #[test]
+    fn not_an_index() {
+        assert!(parse(b"", 20).is_none());
+        assert!(parse(b"PACK\0\0\0\x02\0\0\0\0", 20).is_none());
+        assert!(parse(&header(1, 0), 20).is_none());
+        assert!(parse(&header(5, 0), 20).is_none());
+        // A count far beyond the entries that are there.
+        assert!(parse(&header(2, u32::MAX), 20).is_none());
+    }
+
+    #[test]
+    fn split_index_link() {
+        let mut data = header(2, 1);
+        entry(&mut data, 20, "a.txt", false);
+        data.extend_from_slice(b"link");
+        data.extend_from_slice(&20u32.to_be_bytes());
+        data.extend((1..=20).collect::<Vec<u8>>());
+        checksum(&mut data, 20);
+        let (paths, shared) = parse(&data, 20).unwrap();
+        assert_eq!(paths, ["a.txt"]);
+        assert_eq!(shared.as_deref(), Some("0102030405060708090a0b0c0d0e0f1011121314"));
+    }
+
+    #[test]
+    fn sha256() {
+        let mut data = header(2, 2);
+        entry(&mut data, 32, "a.txt", false);
+        entry(&mut data, 32, "b/c.txt", false);
+        checksum(&mut data, 32);
+        let (paths, _) = parse(&data, 32).unwrap();
+        assert_eq!(paths, ["a.txt", "b/c.txt"]);
+    }
+
+    #[test]
+    fn hash_len_from_config() {
+        let td = TempDir::new();
+        td.write("config", b"[core]\n\tbare = false\n");
+        assert_eq!(hash_len(td.path()), 20);
+        td.write("config", b"[extensions]\n\tobjectFormat = sha256\n");
+        assert_eq!(hash_len(td.path()), 32);
+    }
+
+    #[test]
+    fn varint() {
+        assert_eq!(read_varint(&[0x05]), Some((5, 1)));
+        assert_eq!(read_varint(&[0x80, 0x00]), Some((128, 2)));
+        assert_eq!(read_varint(&[0x81, 0x7f, 0xff]), Some((383, 2)));
+        assert_eq!(read_varint(&[0x80]), None);
+        assert_eq!(read_varint(&[]), None);
+        assert_eq!(read_varint(&[0xff; 16]), None);
+    }
+
+    #[test]
+    fn worktrees_and_submodules() {
+        let td = TempDir::new();
+        td.write("main/.git/HEAD", b"ref: refs/heads/main\n");
+        td.write("main/.git/index", &index_v2());
+        td.write("main/.git/worktrees/wt/commondir", b"../..\n");
+        td.write("main/.git/worktrees/wt/index", &index_v2());
+        td.write("main/.git/modules/sub/index", &index_v2());
+        td.write("main/sub/.git", b"gitdir: ../.git/modules/sub\n");
+        td.write("main/sub/README.md", b"");
+        let worktree = td.path().canonicalize().unwrap().join("main/.git/worktrees/wt");
+        td.write("wt/.git", format!("gitdir: {}\n", worktree.display()).as_bytes());
+        td.write("wt/src/main.rs", b"");
+        td.write("wt/notes.txt", b"");
+        td.write("plain/file", b"");
+
+        let repositories = Repositories::new(td.path().to_str().unwrap(), true).unwrap();
+        let repository = |dir: &str| repositories.repository(Path::new(dir)).map(|repository| repository.kind);
+        assert_eq!(repository("main"), Some(Kind::Repository));
+        assert_eq!(repository("main/sub"), Some(Kind::Submodule));
+        assert_eq!(repository("wt/src"), Some(Kind::Worktree));
+        assert_eq!(repository("plain"), None);
+        let work_tree = repositories.repository(Path::new("wt/src")).unwrap().work_tree.clone();
+        assert_eq!(work_tree, td.path().canonicalize().unwrap().join("wt"));
+
+        // Each work tree is read with its own index.
+        assert_eq!(repositories.status(Path::new("wt/src/main.rs"), false), Status::Tracked);
+        assert_eq!(repositories.status(Path::new("wt/src"), true), Status::Tracked);
+        assert_eq!(repositories.status(Path::new("wt/notes.txt"), false), Status::Untracked);
+        assert_eq!(repositories.status(Path::new("main/sub/README.md"), false), Status::Tracked);
+        assert_eq!(repositories.status(Path::new("main/sub"), true), Status::Untracked);
+        assert_eq!(repositories.status(Path::new("plain/file"), false), Status::NotIgnored);
+    }
+}
@@ -0,0 +1,547 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::escape::{escape_path, unescape_path};
+use crate::output::{self, Output};
+use crate::scan::Group;
+use crate::table::{align_columns, format_delta, format_size};
+use crate::{format_human_readable, scan_directory, Config, Mode};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many directories `record` keeps without --top.
+const DEFAULT_TOP: usize = 10;
+/// The trend column never gets wider than this, longer histories are sampled.
+const TREND_WIDTH: usize = 24;
+const TREND_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The options of a scan that `record` uses. It takes all of them, since `Config::parse` reads
+/// them, but rejects the ones that only change what happens with the results.
+const RECORD_OPTIONS: [&str; 16] = [
+    "help",
+    "apparent",
+    "hidden",
+    "max-depth",
+    "summary",
+    "count-links",
+    "one-file-system",
+    "exclude",
+    "exclude-from",
+    "include",
+    "exclude-mode",
+    "top",
+    "threads",
+    "mode",
+    "history",
+    "PATH",
+];
+
+/// One line of the history file, written by `duh history record`.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// The scanned path, made absolute and escaped with `escape_path`.
+    root: String,
+    /// When the scan finished, in seconds since the Unix epoch.
+    timestamp: u64,
+    apparent: bool,
+    #[serde(flatten)]
+    totals: Totals,
+    /// The biggest directories below the root, biggest first.
+    directories: Vec<Directory>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Directory {
+    /// Relative to the root, escaped with `escape_path` so that the path reads back byte for byte.
+    path: String,
+    #[serde(flatten)]
+    totals: Totals,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct Totals {
+    ignored: u64,
+    not_ignored: u64,
+    git_metadata: u64,
+}
+
+impl Totals {
+    fn new(group: &Group) -> Self {
+        Totals {
+            ignored: group.ignored,
+            not_ignored: group.not_ignored,
+            git_metadata: group.git_metadata,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.ignored + self.not_ignored + self.git_metadata
+    }
+}
+
+pub fn command(record_args: impl IntoIterator<Item = Arg>, show_args: impl IntoIterator<Item = Arg>) -> clap::Command {
+    let history = Arg::new("history")
+        .long("history")
+        .value_name("FILE")
+        .value_parser(clap::value_parser!(PathBuf))
+        .action(ArgAction::Set)
+        .help("The history file. Defaults to $XDG_DATA_HOME/duh/history.jsonl, or ~/.local/share/duh/history.jsonl");
+    clap::Command::new("history")
+        .about("Record how big a directory is over time, and show how that changed")
+        .subcommand_required(true)
+        .disable_help_flag(true)
+        .arg(Arg::new("help").long("help").action(ArgAction::Help))
+        .subcommand(
+            clap::Command::new("record")
+                .about("Scan a path and append a summary of it to the history file")
+                .long_about("Scan a path and append a summary of it to the history file: its ignored, not ignored and git metadata bytes, and those of the biggest directories below it. Takes the options of a scan that decide what is counted, like --hidden, --exclude, -x or --apparent. --top N keeps the N biggest directories instead of 10, and -d picks how deep they can be, 1 by default. Meant to be run from cron, there is no service involved.")
+                .disable_help_flag(true)
+                .args(record_args)
+                .arg(history.clone())
+                .arg(
+                    Arg::new("PATH")
+                        .default_value("./")
+                        .action(ArgAction::Set)
+                        .help("The path to scan"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("show")
+                .about("Show how a recorded path and its biggest directories changed over time")
+                .disable_help_flag(true)
+                .args(show_args)
+                .arg(history)
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .value_name("AGE")
+                        .value_parser(parse_age)
+                        .action(ArgAction::Set)
+                        .help("Only use the summaries recorded in the last AGE, like 12h, 30d or 2w"),
+                )
+                .arg(
+                    Arg::new("PATH")
+                        .default_value("./")
+                        .action(ArgAction::Set)
+                        .help("The path that was recorded"),
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("record", matches)) => record(matches),
+        Some(("show", matches)) => show(matches),
+        _ => unreachable!("history requires a subcommand"),
+    }
+}
+
+fn record(matches: &ArgMatches) {
+    let path = matches.get_one::<String>("PATH").unwrap();
+    let file = history_file(matches);
+    let given = matches
+        .ids()
+        .map(|id| id.as_str())
+        .find(|id| !RECORD_OPTIONS.contains(id) && matches.value_source(id) == Some(ValueSource::CommandLine));
+    if let Some(id) = given {
+        let option = match id {
+            "show-files" => "-a".to_owned(),
+            "human-readable" => "-h".to_owned(),
+            id => format!("--{}", id),
+        };
+        eprintln!("history record doesn't take {}, it only appends a summary to the history file", option);
+        std::process::exit(1);
+    }
+    let mut config = Config::parse(matches.clone());
+    if matches.value_source("mode") == Some(ValueSource::CommandLine) && config.mode != Mode::Du {
+        eprintln!("The history always has both the ignored and the not ignored bytes, leave out --mode");
+        std::process::exit(1);
+    }
+    config.mode = Mode::Du;
+    config.use_recursive_size = true;
+    config.max_depth = Some(config.max_depth.unwrap_or(1));
+    let top = config.top.take().map_or(DEFAULT_TOP, |top| top.count);
+
+    let aggregate = scan_directory(path, &config);
+    let mut directories: Vec<(&PathBuf, &Group)> = aggregate
+        .groups
+        .iter()
+        .filter(|(path, group)| !group.is_file && !path.as_os_str().is_empty())
+        .collect();
+    directories.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(b.0)));
+    directories.truncate(top);
+    let record = Record {
+        root: absolute(path),
+        timestamp: now(),
+        apparent: config.use_apparent_size,
+        totals: aggregate.groups.get(Path::new("")).map(Totals::new).unwrap_or_default(),
+        directories: directories
+            .into_iter()
+            .map(|(path, group)| Directory {
+                path: escape_path(path),
+                totals: Totals::new(group),
+            })
+            .collect(),
+    };
+
+    let mut line = serde_json::to_string(&record).unwrap();
+    line.push('\n');
+    let appended = file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| OpenOptions::new().create(true).append(true).open(&file))
+        // A single write, so that a line never ends up half written next to another one.
+        .and_then(|mut history| history.write_all(line.as_bytes()));
+    if let Err(err) = appended {
+        eprintln!("{}: {}", file.display(), err);
+        std::process::exit(1);
+    }
+    eprintln!(
+        "Recorded {} in {} to {}",
+        format_human_readable(record.totals.total()).trim_start(),
+        record.root,
+        file.display()
+    );
+}
+
+/// A row of the trend table: one recorded path, with what each record had for it.
+struct Row {
+    path: PathBuf,
+    totals: Vec<Option<Totals>>,
+}
+
+impl Row {
+    fn first(&self) -> Option<Totals> {
+        *self.totals.first().unwrap()
+    }
+
+    fn last(&self) -> Option<Totals> {
+        *self.totals.last().unwrap()
+    }
+
+    fn change(&self) -> u64 {
+        match (self.first(), self.last()) {
+            (Some(first), Some(last)) => first.total().abs_diff(last.total()),
+            _ => 0,
+        }
+    }
+}
+
+fn show(matches: &ArgMatches) {
+    let path = matches.get_one::<String>("PATH").unwrap();
+    let file = history_file(matches);
+    let root = absolute(path);
+    let since = matches.get_one::<u64>("since").map(|age| now().saturating_sub(*age));
+    let oldest = since.unwrap_or(0);
+
+    let text = match std::fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("{}: nothing recorded yet, see duh history record", file.display());
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let mut records: Vec<Record> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(line) {
+            Ok(record) => records.push(record),
+            Err(err) => eprintln!("{}:{}: skipped, {}", file.display(), number + 1, err),
+        }
+    }
+    records.retain(|record| record.root == root && record.timestamp >= oldest);
+    records.sort_by_key(|record| record.timestamp);
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        eprintln!("{}: nothing recorded for {}{}", file.display(), root, if since.is_some() { " in that time" } else { "" });
+        std::process::exit(1);
+    };
+
+    eprintln!(
+        "History of {} from {} to {}, {} {}",
+        root,
+        format_date(first.timestamp),
+        format_date(last.timestamp),
+        records.len(),
+        if records.len() == 1 { "record" } else { "records" }
+    );
+    if records.iter().any(|record| record.apparent != first.apparent) {
+        eprintln!("Note: some records have apparent sizes and others disk usage, see --apparent");
+    }
+
+    let rows = rows(&records);
+    let human_readable = matches.get_flag("human-readable");
+    let mut out = Output::new(matches.get_one::<PathBuf>("output").map(PathBuf::as_path));
+    if let Err(err) = print_rows(&rows, path, human_readable, &mut out).and_then(|()| out.flush()) {
+        output::exit_on_error(err);
+    }
+}
+
+/// The root and every directory of `records`, oldest record first. The root stays on top, the
+/// directories that changed the most come right below it.
+fn rows(records: &[Record]) -> Vec<Row> {
+    let mut rows = vec![Row {
+        path: PathBuf::new(),
+        totals: records.iter().map(|record| Some(record.totals)).collect(),
+    }];
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        for directory in &record.directories {
+            let row = *index.entry(&directory.path).or_insert_with(|| {
+                rows.push(Row {
+                    // Records written before paths were escaped have them as they are.
+                    path: unescape_path(&directory.path).unwrap_or_else(|| PathBuf::from(&directory.path)),
+                    totals: vec![None; records.len()],
+                });
+                rows.len() - 1
+            });
+            rows[row].totals[i] = Some(directory.totals);
+        }
+    }
+    rows[1..].sort_by(|a, b| {
+        b.change()
+            .cmp(&a.change())
+            .then_with(|| b.last().map(|last| last.total()).cmp(&a.last().map(|last| last.total())))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    rows
+}
+
+fn print_rows(rows: &[Row], root: &str, human_readable: bool, out: &mut Output) -> io::Result<()> {
+    let header = ["first", "last", "change", "ignored", "not-ignored", "trend"].map(String::from);
+    let cells: Vec<[String; 6]> = std::iter::once(header)
+        .chain(rows.iter().map(|row| {
+            let (first, last) = (row.first(), row.last());
+            let delta = |size: fn(&Totals) -> u64| match (first, last) {
+                (Some(first), Some(last)) => format_delta(size(&first), size(&last), human_readable),
+                _ => "-".to_owned(),
+            };
+            [
+                format_size(first.map(|first| first.total()), human_readable),
+                format_size(last.map(|last| last.total()), human_readable),
+                delta(Totals::total),
+                delta(|totals| totals.ignored),
+                delta(|totals| totals.not_ignored),
+                trend(&row.totals),
+            ]
+        }))
+        .collect();
+
+    let lines = align_columns(&cells);
+    writeln!(out, "{} path", lines[0])?;
+    for (row, line) in rows.iter().zip(&lines[1..]) {
+        writeln!(out, "{} {}", line, Path::new(root).join(&row.path).display())?;
+    }
+    Ok(())
+}
+
+/// A bar per record, from the smallest total of the row to the biggest. A record that didn't
+/// have the directory among its biggest ones is a space.
+fn trend(totals: &[Option<Totals>]) -> String {
+    let sizes: Vec<Option<u64>> = totals.iter().map(|totals| totals.map(|totals| totals.total())).collect();
+    let min = sizes.iter().flatten().min().copied().unwrap_or(0);
+    let max = sizes.iter().flatten().max().copied().unwrap_or(0);
+    let width = sizes.len().min(TREND_WIDTH);
+    (0..width)
+        .map(|i| {
+            // Spread the samples evenly, always keeping the first and the last record.
+            let sample = if width == 1 { sizes.len() - 1 } else { i * (sizes.len() - 1) / (width - 1) };
+            match sizes[sample] {
+                Some(_) if max == min => TREND_BARS[0],
+                Some(size) => TREND_BARS[((size - min) as u128 * 7 / (max - min) as u128) as usize],
+                None => ' ',
+            }
+        })
+        .collect()
+}
+
+fn history_file(matches: &ArgMatches) -> PathBuf {
+    if let Some(file) = matches.get_one::<PathBuf>("history") {
+        return file.clone();
+    }
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    match data_home {
+        Some(dir) => dir.join("duh").join("history.jsonl"),
+        None => {
+            eprintln!("Neither XDG_DATA_HOME nor HOME is set, pass --history FILE");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The same path is recorded from different working directories, so records are kept by the
+/// absolute path.
+fn absolute(path: &str) -> String {
+    Path::new(path)
+        .canonicalize()
+        .map_or_else(|_| escape_path(Path::new(path)), |absolute| escape_path(&absolute))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// An age like `30d`: a number followed by s, m, h, d or w. A number alone is in days.
+fn parse_age(text: &str) -> Result<u64, String> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => text.split_at(at),
+        None => (text, "d"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("unknown unit `{}`, use s, m, h, d or w", unit)),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("expected a number like 30d, got `{}`", text))?;
+    Ok(number.saturating_mul(seconds))
+}
+
+/// The UTC date of a Unix timestamp, as YYYY-MM-DD.
+fn format_date(timestamp: u64) -> String {
+    // Howard Hinnant's civil_from_days, see https://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, parse_age, rows, trend, Directory, Record, Totals, TREND_WIDTH};
+    use crate::escape::escape_path;
+    use std::path::{Path, PathBuf};
+
+    fn totals(not_ignored: u64) -> Totals {
+        Totals {
+            not_ignored,
+            ..Totals::default()
+        }
+    }
+
+    fn record(timestamp: u64, directories: &[(&Path, u64)]) -> Record {
+        Record {
+            root: "/src".to_owned(),
+            timestamp,
+            apparent: true,
+            totals: totals(directories.iter().map(|(_, size)| size).sum()),
+            directories: directories
+                .iter()
+                .map(|(path, size)| Directory {
+                    path: escape_path(path),
+                    totals: totals(*size),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("90s"), Ok(90));
+        assert_eq!(parse_age("10m"), Ok(600));
+        assert_eq!(parse_age("12h"), Ok(12 * 3600));
+        assert_eq!(parse_age("30d"), Ok(30 * 86400));
+        assert_eq!(parse_age("2w"), Ok(14 * 86400));
+        // A number alone is in days.
+        assert_eq!(parse_age("3"), Ok(3 * 86400));
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(86399), "1970-01-01");
+        assert_eq!(format_date(1709164800), "2024-02-29");
+        assert_eq!(format_date(1709164800 + 86400), "2024-03-01");
+        // 2000 is a leap year, 2100 isn't.
+        assert_eq!(format_date(951868800 - 86400), "2000-02-29");
+        assert_eq!(format_date(4107542400 - 86400), "2100-02-28");
+    }
+
+    #[test]
+    fn flat_trend() {
+        assert_eq!(trend(&[Some(totals(5)), Some(totals(5)), Some(totals(5))]), "▁▁▁");
+        assert_eq!(trend(&[Some(totals(5))]), "▁");
+        assert_eq!(trend(&[Some(totals(0)), None, Some(totals(7))]), "▁ █");
+    }
+
+    #[test]
+    fn long_trend_is_sampled() {
+        let sizes: Vec<Option<Totals>> = (0..=70).map(|size| Some(totals(size))).collect();
+        let trend: Vec<char> = trend(&sizes).chars().collect();
+        assert_eq!(trend.len(), TREND_WIDTH);
+        // The first and the last record are always shown.
+        assert_eq!((trend[0], trend[TREND_WIDTH - 1]), ('▁', '█'));
+        assert!(trend.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn directories_missing_from_some_records() {
+        #[cfg(unix)]
+        let odd = {
+            use std::os::unix::ffi::OsStrExt;
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"odd\xff"))
+        };
+        #[cfg(not(unix))]
+        let odd = PathBuf::from("odd");
+        let records = [
+            record(100, &[(Path::new("src"), 10), (Path::new("docs"), 4)]),
+            record(200, &[(Path::new("src"), 30), (&odd, 8)]),
+        ];
+        let rows = rows(&records);
+        let summary: Vec<(PathBuf, Vec<Option<u64>>)> = rows
+            .iter()
+            .map(|row| {
+                let sizes = row.totals.iter().map(|totals| totals.map(|totals| totals.total())).collect();
+                (row.path.clone(), sizes)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (PathBuf::new(), vec![Some(14), Some(38)]),
+                (PathBuf::from("src"), vec![Some(10), Some(30)]),
+                // Not in both records, so there is no change to sort by.
+                (odd, vec![None, Some(8)]),
+                (PathBuf::from("docs"), vec![Some(4), None]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,444 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::scan::Group;
+use crate::threshold::parse_size;
+use crate::{compare_components, file_types, visualize};
+use clap::{Arg, ArgAction, ArgMatches};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+enum Format {
+    /// The output of `du -a`, one `SIZE<tab>PATH` line per path. du doesn't say which paths are
+    /// directories, so an empty directory shows up as an empty file
+    Du,
+    /// An export of ncdu, made with `ncdu -o FILE` or `duh --format ncdu`
+    Ncdu,
+}
+
+/// A path found in the imported file, with the bytes of the path itself, not counting anything
+/// below it.
+struct Imported {
+    path: PathBuf,
+    size: u64,
+    is_file: bool,
+}
+
+#[derive(Copy, PartialEq, Eq, Clone)]
+enum Kind {
+    NotIgnored,
+    Ignored,
+    GitMetadata,
+}
+
+pub fn command(args: impl IntoIterator<Item = Arg>) -> clap::Command {
+    clap::Command::new("import")
+        .about("Show the output of du or an ncdu export in the browser")
+        .long_about("Show the output of `du -a` or an ncdu export in the browser, for machines duh can't run on. Everything counts as not ignored, unless --gitignore-root points at a directory with the same layout, like a checkout of the same repository. Then the .gitignore files found there decide what is ignored, and .git directories count as git metadata.")
+        .disable_help_flag(true)
+        .args(args)
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .required(true)
+                .value_parser(clap::value_parser!(Format))
+                .action(ArgAction::Set)
+                .help("The format of FILE"),
+        )
+        .arg(
+            Arg::new("block-size")
+                .long("block-size")
+                .value_name("SIZE")
+                .value_parser(parse_size)
+                .default_value("1K")
+                .action(ArgAction::Set)
+                .help("The unit of the sizes in the output of du. The default fits `du -ak`, use 1 for `du -ab`."),
+        )
+        .arg(
+            Arg::new("gitignore-root")
+                .long("gitignore-root")
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("Tell ignored and not ignored paths apart with the .gitignore files in DIR, which stands in for the root of the imported paths"),
+        )
+        .arg(
+            Arg::new("FILE")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("The file to import"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) {
+    let file = matches.get_one::<PathBuf>("FILE").unwrap();
+    let text = match std::fs::read(file) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => {
+            eprintln!("{}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let parsed = match matches.get_one::<Format>("from").unwrap() {
+        Format::Du => parse_du(&text, *matches.get_one::<u64>("block-size").unwrap()),
+        Format::Ncdu => parse_ncdu(&text),
+    };
+    let (root, mut imported) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    // Path order puts every directory before everything below it, so an ignored directory is
+    // classified before its contents.
+    imported.sort_by(|a, b| a.path.cmp(&b.path));
+    let kinds: Vec<Kind> = match matches.get_one::<PathBuf>("gitignore-root") {
+        Some(gitignore_root) => classify(&imported, gitignore_root),
+        None => vec![Kind::NotIgnored; imported.len()],
+    };
+
+    let mut groups: HashMap<PathBuf, Group> = HashMap::new();
+    for (entry, kind) in imported.iter().zip(kinds) {
+        groups.entry(entry.path.clone()).or_default().is_file = entry.is_file;
+        for ancestor in entry.path.ancestors() {
+            let group = groups.entry(ancestor.to_path_buf()).or_default();
+            match kind {
+                Kind::NotIgnored => group.not_ignored += entry.size,
+                Kind::Ignored => group.ignored += entry.size,
+                Kind::GitMetadata => group.git_metadata += entry.size,
+            }
+            if entry.is_file {
+                group.count += 1;
+            }
+        }
+    }
+
+    let total = groups.get(Path::new("")).map_or(0, Group::total);
+    eprintln!(
+        "Imported {} paths below {}, {} in total",
+        imported.len(),
+        root,
+        crate::format_human_readable(total).trim_start()
+    );
+    let mut pairs: Vec<(PathBuf, Group)> = groups.into_iter().collect();
+    // visualize::encode expects every directory to be immediately followed by its children.
+    pairs.sort_by(|a, b| compare_components(&a.0, &b.0, Ordering::Greater));
+    let types = file_types(matches);
+    visualize::view_in_browser(&pairs, Some(&types), None, matches.get_flag("open"));
+}
+
+/// Read the output of `du -a`. du prints a directory after its contents, so the last line is
+/// the path it was given, and every other path is below it. Directories are the paths something
+/// else is below, and their sizes include their contents. An empty directory has nothing below
+/// it, so it can't be told apart from a file.
+fn parse_du(text: &str, block_size: u64) -> Result<(String, Vec<Imported>), String> {
+    let mut lines: Vec<(u64, &str)> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = line
+            .split_once('\t')
+            .and_then(|(size, path)| Some((size.trim().parse::<u64>().ok()?, path)));
+        match parsed {
+            Some((size, path)) => lines.push((size.saturating_mul(block_size), path)),
+            None => return Err(format!("line {}: expected SIZE<tab>PATH, like du prints", number + 1)),
+        }
+    }
+    let Some(&(_, root)) = lines.last() else {
+        return Err("no paths in it".to_owned());
+    };
+
+    let mut imported = Vec::with_capacity(lines.len());
+    for (size, path) in lines {
+        let Ok(relative) = Path::new(path).strip_prefix(root) else {
+            return Err(format!("{} is not below {}, import the output of du for one path at a time", path, root));
+        };
+        imported.push(Imported {
+            path: relative.to_path_buf(),
+            size,
+            is_file: true,
+        });
+    }
+
+    // Take the sizes of their contents off the directories.
+    let index: HashMap<PathBuf, usize> = imported
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.path.clone(), i))
+        .collect();
+    let recursive: Vec<u64> = imported.iter().map(|entry| entry.size).collect();
+    for (i, size) in recursive.into_iter().enumerate() {
+        let parent = match imported[i].path.parent().and_then(|parent| index.get(parent)) {
+            Some(&parent) if parent != i => parent,
+            _ => continue,
+        };
+        imported[parent].size = imported[parent].size.saturating_sub(size);
+        imported[parent].is_file = false;
+    }
+    Ok((root.to_owned(), imported))
+}
+
+/// Read an ncdu export, see https://dev.yorhel.nl/ncdu/jsonfmt. The sizes in it are already
+/// those of each entry itself. Disk usage is used when it's there, the apparent size otherwise.
+fn parse_ncdu(text: &str) -> Result<(String, Vec<Imported>), String> {
+    let export: Value = serde_json::from_str(text).map_err(|err| format!("not an ncdu export: {}", err))?;
+    let root = match export.as_array().map(Vec::as_slice) {
+        Some([Value::Number(major), _, _, root, ..]) if major.as_u64() == Some(1) => root,
+        Some([Value::Number(major), ..]) => return Err(format!("ncdu export version {} isn't supported", major)),
+        _ => return Err("not an ncdu export".to_owned()),
+    };
+    let Some(Value::Object(info)) = root.as_array().and_then(|root| root.first()) else {
+        return Err("not an ncdu export, the root isn't a directory".to_owned());
+    };
+    let root_name = info.get("name").and_then(Value::as_str).unwrap_or_default().to_owned();
+
+    let mut imported = Vec::new();
+    // Directories still to be read, with their path relative to the root.
+    let mut directories: Vec<(PathBuf, &Value)> = vec![(PathBuf::new(), root)];
+    while let Some((path, directory)) = directories.pop() {
+        let mut items = directory.as_array().unwrap().iter();
+        imported.push(Imported {
+            path: path.clone(),
+            size: items.next().map_or(0, size),
+            is_file: false,
+        });
+        for item in items {
+            let name = match item {
+                Value::Array(directory) => directory.first(),
+                info => Some(info),
+            }
+            .and_then(|info| info.get("name"))
+            .and_then(Value::as_str);
+            let Some(name) = name else {
+                return Err(format!("an entry in {} has no name", path.display()));
+            };
+            match item {
+                Value::Array(_) => directories.push((path.join(name), item)),
+                info => imported.push(Imported {
+                    path: path.join(name),
+                    size: size(info),
+                    is_file: true,
+                }),
+            }
+        }
+    }
+    Ok((root_name, imported))
+}
+
+fn size(info: &Value) -> u64 {
+    info.get("dsize")
+        .or_else(|| info.get("asize"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0)
+}
+
+/// Classify each of `imported`, sorted by path, with the .gitignore files found in the same
+/// directories below `gitignore_root`. Like git, the .gitignore closest to a path decides, and
+/// everything below an ignored directory is ignored.
+fn classify(imported: &[Imported], gitignore_root: &Path) -> Vec<Kind> {
+    let mut gitignores: HashMap<&Path, Gitignore> = HashMap::new();
+    for entry in imported.iter().filter(|entry| !entry.is_file) {
+        let directory = gitignore_root.join(&entry.path);
+        let file = directory.join(".gitignore");
+        if !file.is_file() {
+            continue;
+        }
+        let mut builder = GitignoreBuilder::new(&directory);
+        if let Some(err) = builder.add(&file) {
+            eprintln!("{}", err);
+        }
+        match builder.build() {
+            Ok(gitignore) => {
+                gitignores.insert(entry.path.as_path(), gitignore);
+            }
+            Err(err) => eprintln!("{}: {}", file.display(), err),
+        }
+    }
+    if gitignores.is_empty() {
+        eprintln!("Note: no .gitignore files in {}, so nothing is ignored", gitignore_root.display());
+    }
+
+    let mut kinds: HashMap<&Path, Kind> = HashMap::new();
+    for entry in imported {
+        let inherited = entry.path.parent().and_then(|parent| kinds.get(parent)).copied();
+        let kind = if entry.path.file_name().is_some_and(|name| name == ".git") {
+            Kind::GitMetadata
+        } else if let Some(kind @ (Kind::Ignored | Kind::GitMetadata)) = inherited {
+            kind
+        } else {
+            let path = gitignore_root.join(&entry.path);
+            let decided = entry
+                .path
+                .ancestors()
+                .skip(1)
+                .filter_map(|directory| gitignores.get(directory))
+                .map(|gitignore| gitignore.matched(&path, !entry.is_file))
+                .find(|matched| !matched.is_none());
+            match decided {
+                Some(Match::Ignore(_)) => Kind::Ignored,
+                _ => Kind::NotIgnored,
+            }
+        };
+        kinds.insert(&entry.path, kind);
+    }
+    imported.iter().map(|entry| kinds[entry.path.as_path()]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, parse_du, parse_ncdu, Imported, Kind};
+    use crate::tests::TempDir;
+    use std::path::{Path, PathBuf};
+
+    /// The path, size and whether it's a file of each entry, in path order.
+    fn summary(mut imported: Vec<Imported>) -> Vec<(String, u64, bool)> {
+        imported.sort_by(|a, b| a.path.cmp(&b.path));
+        imported
+            .into_iter()
+            .map(|entry| (entry.path.to_str().unwrap().to_owned(), entry.size, entry.is_file))
+            .collect()
+    }
+
+    #[test]
+    fn du() {
+        let text = "4\tproject/src/main.rs\n0\tproject/src/empty\n12\tproject/src\n8\tproject/README.md\n24\tproject\n";
+        let (root, imported) = parse_du(text, 1024).unwrap();
+        assert_eq!(root, "project");
+        assert_eq!(
+            summary(imported),
+            [
+                ("".to_owned(), 4096, false),
+                ("README.md".to_owned(), 8192, true),
+                ("src".to_owned(), 8192, false),
+                // An empty directory, which du can't tell apart from an empty file.
+                ("src/empty".to_owned(), 0, true),
+                ("src/main.rs".to_owned(), 4096, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn du_root_not_last() {
+        // The output of `du -a project other`, or of a du that was cut short.
+        let text = "4\tproject/a\n8\tproject\n4\tother/b\n";
+        let err = parse_du(text, 1).err().unwrap();
+        assert!(err.contains("project/a is not below other/b"), "{}", err);
+    }
+
+    #[test]
+    fn du_not_du() {
+        assert!(parse_du("", 1).is_err());
+        assert!(parse_du("4\tproject\nproject/a\n", 1).err().unwrap().starts_with("line 2"));
+        assert!(parse_du("4K\tproject\n", 1).is_err());
+    }
+
+    #[test]
+    fn ncdu_nested() {
+        let text = r#"[1, 2, {"progname": "ncdu", "progver": "1.19"},
+            [{"name": "/home/me", "dsize": 4096},
+                {"name": "a.txt", "asize": 10, "dsize": 4096},
+                [{"name": "sub", "dsize": 4096},
+                    {"name": "b.bin", "asize": 300},
+                    [{"name": "deeper"},
+                        {"name": "c", "dsize": 8192}]],
+                [{"name": "empty", "dsize": 4096}]]]"#;
+        let (root, imported) = parse_ncdu(text).unwrap();
+        assert_eq!(root, "/home/me");
+        assert_eq!(
+            summary(imported),
+            [
+                ("".to_owned(), 4096, false),
+                ("a.txt".to_owned(), 4096, true),
+                ("empty".to_owned(), 4096, false),
+                ("sub".to_owned(), 4096, false),
+                ("sub/b.bin".to_owned(), 300, true),
+                ("sub/deeper".to_owned(), 0, false),
+                ("sub/deeper/c".to_owned(), 8192, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn ncdu_version() {
+        let err = parse_ncdu(r#"[2, 0, {}, [{"name": "/"}]]"#).err().unwrap();
+        assert_eq!(err, "ncdu export version 2 isn't supported");
+        assert!(parse_ncdu(r#"{"name": "/"}"#).is_err());
+        assert!(parse_ncdu(r#"[1, 0, {}, {"name": "/"}]"#).is_err());
+        assert!(parse_ncdu("du output").is_err());
+    }
+
+    #[test]
+    fn ncdu_missing_name() {
+        let text = r#"[1, 0, {}, [{"name": "/"}, [{"name": "dir"}, {"dsize": 4096}]]]"#;
+        let err = parse_ncdu(text).err().unwrap();
+        assert_eq!(err, "an entry in dir has no name");
+        let text = r#"[1, 0, {}, [{"name": "/"}, [{"dsize": 4096}]]]"#;
+        assert!(parse_ncdu(text).is_err());
+    }
+
+    #[test]
+    fn classify_with_gitignores() {
+        let td = TempDir::new();
+        td.write(".gitignore", b"*.log\nbuild/\n");
+        td.write("sub/.gitignore", b"!keep.log\n");
+        let paths = [
+            ("", false, Kind::NotIgnored),
+            (".git", false, Kind::GitMetadata),
+            (".git/objects", false, Kind::GitMetadata),
+            (".git/objects/ab", true, Kind::GitMetadata),
+            (".gitignore", true, Kind::NotIgnored),
+            ("a.log", true, Kind::Ignored),
+            ("build", false, Kind::Ignored),
+            ("build/keep.log", true, Kind::Ignored),
+            ("build/out", true, Kind::Ignored),
+            ("src", false, Kind::NotIgnored),
+            ("src/main.rs", true, Kind::NotIgnored),
+            ("sub", false, Kind::NotIgnored),
+            ("sub/keep.log", true, Kind::NotIgnored),
+            ("sub/other.log", true, Kind::Ignored),
+        ];
+        let imported: Vec<Imported> = paths
+            .iter()
+            .map(|&(path, is_file, _)| Imported {
+                path: PathBuf::from(path),
+                size: 1,
+                is_file,
+            })
+            .collect();
+        let kinds = classify(&imported, td.path());
+        for ((path, _, expected), kind) in paths.iter().zip(kinds) {
+            assert!(kind == *expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn classify_without_gitignores() {
+        let imported = [Imported {
+            path: PathBuf::from("a.log"),
+            size: 1,
+            is_file: true,
+        }];
+        let kinds = classify(&imported, Path::new("/nonexistent/duh"));
+        assert!(kinds == [Kind::NotIgnored]);
+    }
+}
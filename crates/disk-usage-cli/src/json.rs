@@ -0,0 +1,185 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::Output;
+use crate::scan::Group;
+use crate::{Config, OutputFormat};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One path in the output of --format json and ndjson.
+///
+/// The ignored, not ignored, tracked, untracked and .git bytes are disk usage, or apparent
+/// size with --apparent. `apparent_bytes` and `disk_bytes` are always both there.
+#[derive(Serialize)]
+struct Record {
+    path: String,
+    /// Number of components below the scanned root, which is at depth 0.
+    depth: usize,
+    is_file: bool,
+    /// The size that counts in the selected mode.
+    bytes: u64,
+    not_ignored_bytes: u64,
+    ignored_bytes: u64,
+    git_metadata_bytes: u64,
+    /// Only known when tracked and untracked files are told apart, see `Config::git_status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracked_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    untracked_bytes: Option<u64>,
+    files: u64,
+    apparent_bytes: u64,
+    disk_bytes: u64,
+}
+
+impl Record {
+    fn new(relative_path: &Path, group: &Group, root: &str, config: &Config) -> Self {
+        Record {
+            path: Path::new(root).join(relative_path).to_string_lossy().into_owned(),
+            depth: relative_path.components().count(),
+            is_file: group.is_file,
+            bytes: group.size(config.mode),
+            not_ignored_bytes: group.not_ignored,
+            ignored_bytes: group.ignored,
+            git_metadata_bytes: group.git_metadata,
+            tracked_bytes: config.git_status.then_some(group.tracked),
+            untracked_bytes: config.git_status.then_some(group.untracked),
+            files: group.count,
+            apparent_bytes: group.apparent_size,
+            disk_bytes: group.disk_size,
+        }
+    }
+}
+
+/// A record with the records of the entries directly below it.
+#[derive(Serialize)]
+struct Node {
+    #[serde(flatten)]
+    record: Record,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Node>,
+}
+
+/// The output of --format json.
+#[derive(Serialize)]
+struct Document<'a> {
+    root: &'a str,
+    mode: &'static str,
+    apparent: bool,
+    /// Entries whose parent isn't listed. Usually that is only the root.
+    entries: Vec<Node>,
+}
+
+/// Write `entries` to `out` in the format selected with --format. Ndjson keeps the order of
+/// `entries`, json nests every entry under its parent and keeps the order among siblings.
+pub fn write(entries: &[(PathBuf, Group)], root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Ndjson => {
+            for (path, group) in entries {
+                serde_json::to_writer(&mut *out, &Record::new(path, group, root, config))?;
+                out.write_all(b"\n")?;
+            }
+        }
+        OutputFormat::Json => {
+            let document = Document {
+                root,
+                mode: config.mode.name(),
+                apparent: config.use_apparent_size,
+                entries: nest(entries, root, config),
+            };
+            serde_json::to_writer(&mut *out, &document)?;
+            out.write_all(b"\n")?;
+        }
+        _ => unreachable!("only json and ndjson are written here"),
+    }
+    Ok(())
+}
+
+fn nest(entries: &[(PathBuf, Group)], root: &str, config: &Config) -> Vec<Node> {
+    let index: HashMap<&Path, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (path, _))| (path.as_path(), i))
+        .collect();
+    let mut roots = vec![];
+    let mut children: Vec<Vec<usize>> = vec![vec![]; entries.len()];
+    for (i, (path, _)) in entries.iter().enumerate() {
+        match path.parent().and_then(|parent| index.get(parent)) {
+            Some(&parent) => children[parent].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    // Build the deepest nodes first, so the children of a node are done before it. This
+    // doesn't recurse, since the tree can be as deep as the file system.
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&i| Reverse(entries[i].0.components().count()));
+    let mut nodes: Vec<Option<Node>> = entries.iter().map(|_| None).collect();
+    for i in order {
+        let (path, group) = &entries[i];
+        nodes[i] = Some(Node {
+            record: Record::new(path, group, root, config),
+            children: children[i].iter().filter_map(|&child| nodes[child].take()).collect(),
+        });
+    }
+    roots.into_iter().filter_map(|i| nodes[i].take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nest, Node};
+    use crate::scan::Group;
+    use crate::{command, Config};
+    use std::path::PathBuf;
+
+    fn entries(paths: &[&str]) -> Vec<(PathBuf, Group)> {
+        paths.iter().map(|path| (PathBuf::from(path), Group::default())).collect()
+    }
+
+    /// Each node as `path[children]`.
+    fn outline(nodes: &[Node]) -> String {
+        let outlines: Vec<String> = nodes
+            .iter()
+            .map(|node| match node.children.as_slice() {
+                [] => node.record.path.clone(),
+                children => format!("{}[{}]", node.record.path, outline(children)),
+            })
+            .collect();
+        outlines.join(" ")
+    }
+
+    #[test]
+    fn nested_in_order() {
+        let config = Config::parse(command().get_matches_from(["duh", "--format", "json"]));
+        // The order du prints them in, every directory after its contents.
+        let entries = entries(&["a/x", "a/y/z", "a/y", "a", "b", ""]);
+        assert_eq!(outline(&nest(&entries, "root", &config)), "root/[root/a[root/a/x root/a/y[root/a/y/z]] root/b]");
+        let depths: Vec<usize> = nest(&entries, "root", &config)[0]
+            .children
+            .iter()
+            .map(|node| node.record.depth)
+            .collect();
+        assert_eq!(depths, [1, 1]);
+    }
+
+    #[test]
+    fn missing_parents() {
+        let config = Config::parse(command().get_matches_from(["duh", "--format", "json"]));
+        let entries = entries(&["a/x/1", "a/x", "b/y", ""]);
+        assert_eq!(outline(&nest(&entries, ".", &config)), "./a/x[./a/x/1] ./b/y ./");
+    }
+}
@@ -12,15 +12,43 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use clap::{Arg, ArgAction};
-use ignore::WalkBuilder;
-use indexmap::IndexMap;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ValueEnum};
+use ignore::overrides::OverrideBuilder;
+use ignore::types::{Types, TypesBuilder};
+use ignore::{IgnoreKind, IgnoreReason, WalkBuilder};
 use indicatif::{ProgressBar, ProgressStyle};
 use num_format::{Locale, SystemLocale, ToFormattedString};
+use output::Output;
+use scan::{ExtensionSizes, GitMetadata, Group, TypeSizes};
+use serde::Serialize;
+use sort::{Sort, SortKey};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::sync::Arc;
 
+mod csv;
+mod diff;
+mod escape;
+mod folded;
+mod git;
+mod history;
+mod import;
+mod json;
+mod mounts;
+mod ncdu;
+mod output;
+mod scan;
+mod snapshot;
+mod sort;
+mod table;
+mod threshold;
+mod tree;
 mod visualize;
+mod why;
 
 /// Format a number with locale-aware thousands separators
 fn format_number(num: u64) -> String {
@@ -34,7 +62,8 @@ fn format_number(num: u64) -> String {
     }
 }
 
-fn main() {
+/// duh and its options, without the subcommands.
+fn command() -> clap::Command {
     let mut command = clap::Command::new("duh")
         .version("0.1.0")
         .author("nobody <nobody@localhost>")
@@ -73,12 +102,191 @@ fn main() {
                 .action(ArgAction::Set)
                 .help("Display an entry for all files and directories depth directories deep."),
         )
+        .arg(
+            Arg::new("count-links")
+                .short('l')
+                .long("count-links")
+                .action(ArgAction::SetTrue)
+                .help("Count the size of a hard linked file once for every link, instead of only once. When only some links to a file are ignored, the file is counted as not ignored, since deleting the ignored links frees no space."),
+        )
+        .arg(
+            Arg::new("one-file-system")
+                .short('x')
+                .long("one-file-system")
+                .action(ArgAction::SetTrue)
+                .help("Skip directories on different file systems. The mount points that were skipped are listed at the end of the scan."),
+        )
+        .args(glob_args())
+        .arg(
+            Arg::new("exclude-mode")
+                .long("exclude-mode")
+                .value_name("MODE")
+                .default_value("ignored")
+                .value_parser(clap::value_parser!(ExcludeMode))
+                .action(ArgAction::Set)
+                .help("What happens to paths excluded by --exclude, --exclude-from or --include. 'ignored' counts them as ignored, so they show up in --mode ignored and not in --mode not-ignored. 'omitted' leaves them out entirely, as if they didn't exist."),
+        )
         .arg(
             Arg::new("direct-size")
                 .long("direct-size")
                 .action(ArgAction::SetTrue)
                 .help("Only calculate size using direct contents of the directory. This can be helpful when trying to find what is using all that disk space"),
         )
+        .arg(
+            Arg::new("breakdown")
+                .long("breakdown")
+                .action(ArgAction::SetTrue)
+                .help("Print the total, ignored, tracked, untracked and .git size of each entry in separate columns. Tracked files are the ones in the index of a git repository, which is read directly without running git. Untracked files are in a repository, but neither ignored nor tracked."),
+        )
+        .arg(
+            Arg::new("by-rule")
+                .long("by-rule")
+                .action(ArgAction::SetTrue)
+                .help("Instead of listing paths, show how much space is ignored by each rule, e.g. a single line of a .gitignore file. Files inside an ignored directory count towards the rule that ignored the directory. `.git` directories aren't a rule, they are in the git metadata summary instead."),
+        )
+        .arg(
+            Arg::new("by-type")
+                .long("by-type")
+                .action(ArgAction::SetTrue)
+                .help("Instead of listing paths, show how much space the files of each file type use, like ripgrep's --type-list. A file that matches several types counts towards the first one in alphabetical order. Files in .git directories are left out."),
+        )
+        .arg(
+            Arg::new("type-add")
+                .long("type-add")
+                .value_name("TYPE_SPEC")
+                .action(ArgAction::Append)
+                .help("Add a file type for --by-type and the browser, as name:glob. Can be given more than once, the same name adds more globs. name:include:other,types combines existing types. Files matching a type added here count towards it before any built-in type."),
+        )
+        .arg(
+            Arg::new("tree")
+                .long("tree")
+                .action(ArgAction::SetTrue)
+                .help("Print the entries as a tree, with each entry's share of its parent and a bar split into not ignored (█), ignored (░) and .git (▒) bytes. Fits the width of the terminal. Combine with --depth, -t and --sort to keep it short."),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .default_value("text")
+                .value_parser(clap::value_parser!(OutputFormat))
+                .action(ArgAction::Set)
+                .help("Print the entries on stdout in a format for scripts instead of as text. json is one document with the entries nested under their directories, ndjson is one record per line in the same order as the text. Records have the path, depth, whether it is a file, the ignored, not ignored and .git bytes, the file count, and both the apparent size and the disk usage. csv and tsv print a header row and the --columns of each entry, for spreadsheets. ncdu is ncdu's export format, with every file regardless of --depth, to browse with `ncdu -f`. folded is one `dir;subdir;file bytes` line per entry, for flamegraph.pl or inferno."),
+        )
+        .arg(
+            Arg::new("folded-prefix")
+                .long("folded-prefix")
+                .action(ArgAction::SetTrue)
+                .help("Start each stack of --format folded with ignored, tracked, untracked, not-ignored or git, so that each kind of bytes becomes a tower of its own in the flame graph."),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("Write the results to FILE instead of stdout. Progress and warnings still go to stderr."),
+        )
+        .arg(
+            Arg::new("save")
+                .long("save")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("Also save everything the scan found to FILE, with the options it was scanned with. `duh open FILE` shows it again later, on any machine, without scanning. Without --depth it has every file."),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(csv::Column))
+                .action(ArgAction::Set)
+                .help("The columns of --format csv and tsv, separated by commas. Sizes are in bytes. Defaults to path,ignored,not_ignored,git_metadata,total,count."),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('t')
+                .long("threshold")
+                .value_name("SIZE")
+                .value_parser(threshold::parse_size)
+                .action(ArgAction::Set)
+                .help("Hide entries smaller than SIZE, e.g. 10M. The entries hidden in each directory are listed as one <N smaller entries> line, so the sizes still add up. Applies to the browser too, which makes huge scans much faster to lay out."),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .value_name("KEY")
+                .value_parser(clap::value_parser!(SortKey))
+                .action(ArgAction::Set)
+                .help("Order entries by size, name, count (number of files) or mtime (newest modification time at or below the entry). Sizes, counts and times sort biggest or newest first. Entries are only compared with their siblings, so every directory stays next to its contents, unless --flat is given."),
+        )
+        .arg(
+            Arg::new("reverse")
+                .long("reverse")
+                .action(ArgAction::SetTrue)
+                .help("Reverse the order of --sort. Sorts by name when --sort isn't given."),
+        )
+        .arg(
+            Arg::new("flat")
+                .long("flat")
+                .action(ArgAction::SetTrue)
+                .help("Sort all entries against each other instead of keeping directories together. Sorts by name when --sort isn't given."),
+        )
+        .arg(
+            Arg::new("top")
+                .long("top")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+                .help("Instead of listing every path, list the N biggest files and directories, biggest first, with their share of the total. Only the N biggest files are kept in memory during the scan. Respects --mode and --depth."),
+        )
+        .arg(
+            Arg::new("top-files")
+                .long("top-files")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+                .help("Like --top, but only list files"),
+        )
+        .arg(
+            Arg::new("top-dirs")
+                .long("top-dirs")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+                .help("Like --top, but only list directories"),
+        )
+        .arg(
+            Arg::new("by-extension")
+                .long("by-extension")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .default_missing_value("table")
+                .require_equals(true)
+                .value_parser(clap::value_parser!(ExtensionFormat))
+                .action(ArgAction::Set)
+                .help("Instead of listing paths, show the total size, file count, mean size and largest file of each file extension. --by-extension=json prints the same as JSON on stdout. Extensions are compared case insensitively. Files in .git directories are left out."),
+        )
+        .arg(
+            Arg::new("repos")
+                .long("repos")
+                .value_name("COLUMN")
+                .num_args(0..=1)
+                .default_missing_value("total")
+                .require_equals(true)
+                .value_parser(clap::value_parser!(RepoColumn))
+                .action(ArgAction::Set)
+                .help("Instead of listing paths, print one row per git work tree with its ignored, not ignored and .git size, sorted by COLUMN (--repos=COLUMN, total by default). Worktrees and submodules get their own row. Entries outside of any work tree are grouped together."),
+        )
+        .arg(
+            Arg::new("threads")
+                .short('j')
+                .long("threads")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+                .help("Number of threads used to walk the file hierarchy. The default of 1 walks on a single thread. Output is identical either way."),
+        )
         .arg(
             Arg::new("web")
                 .long("web")
@@ -124,7 +332,74 @@ fn main() {
                 .long_help("")
                 .help("Specify the paths to analyze"),
         );
-    let matches = command.get_matches();
+    for (i, report) in REPORTS.iter().enumerate() {
+        command = command.mut_arg(report, |arg| {
+            arg.conflicts_with_all(REPORTS[i + 1..].iter().copied().chain(["web", "open"]))
+        });
+    }
+    command
+}
+
+/// The options that print a report of their own instead of the list of paths. Only one of them
+/// can be given, and none of them together with --web or a --format other than text.
+const REPORTS: [&str; 8] = ["by-rule", "by-type", "by-extension", "top", "top-files", "top-dirs", "repos", "tree"];
+
+fn main() {
+    let command = command();
+    // `duh open` shows a snapshot with the same options, except for the paths to scan.
+    let open = snapshot::command(command.get_arguments().filter(|arg| arg.get_id() != "PATH").cloned());
+    let why = why::command(command.get_arguments().filter(|arg| arg.get_id() == "output").cloned());
+    // `duh diff` only needs the options that decide how the changes are listed.
+    let diff_args = ["help", "show-files", "max-depth", "human-readable", "output", "web", "open"];
+    let diff = diff::command(
+        command
+            .get_arguments()
+            .filter(|arg| diff_args.contains(&arg.get_id().as_str()))
+            .cloned(),
+    );
+    // `duh history record` scans like duh does, `duh history show` only lists what was recorded.
+    let history = history::command(
+        command.get_arguments().filter(|arg| arg.get_id() != "PATH").cloned(),
+        command
+            .get_arguments()
+            .filter(|arg| ["help", "human-readable", "output"].contains(&arg.get_id().as_str()))
+            .cloned(),
+    );
+    let import = import::command(
+        command
+            .get_arguments()
+            .filter(|arg| ["help", "open", "type-add"].contains(&arg.get_id().as_str()))
+            .cloned(),
+    );
+    let matches = command
+        .args_conflicts_with_subcommands(true)
+        .subcommand(why)
+        .subcommand(open)
+        .subcommand(diff)
+        .subcommand(history)
+        .subcommand(import)
+        .get_matches();
+
+    if let Some(matches) = matches.subcommand_matches("why") {
+        why::run(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("open") {
+        snapshot::open(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        diff::run(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("history") {
+        history::run(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("import") {
+        import::run(matches);
+        return;
+    }
 
     // TODO change this into get_many
     let mut paths: Vec<String> = matches
@@ -143,15 +418,32 @@ fn main() {
         eprintln!("Cannot open multiple directories in web mode");
         std::process::exit(1);
     }
+    if config.save.is_some() && paths.len() > 1 {
+        eprintln!("Cannot save multiple directories to one snapshot");
+        std::process::exit(1);
+    }
+    if config.save.is_some() && config.keep_entries {
+        eprintln!("Cannot save a snapshot with --format ncdu");
+        std::process::exit(1);
+    }
 
+    let mut out = Output::new(config.output.as_deref());
     for path in paths {
-        process_directory(path.as_str(), &config);
+        if let Err(err) = process_directory(path.as_str(), &config, &mut out) {
+            output::exit_on_error(err);
+        }
+    }
+    if let Err(err) = out.flush() {
+        output::exit_on_error(err);
     }
     //self::visualize::view_in_browser();
 }
 
 struct Config {
     include_hidden: bool,
+    globs: Globs,
+    /// Leave out paths excluded by `globs` instead of counting them as ignored.
+    omit_excluded: bool,
     max_depth: Option<u64>,
     human_readable: bool,
     use_apparent_size: bool,
@@ -160,6 +452,38 @@ struct Config {
     show_only_directories: bool,
     web: bool,
     open: bool,
+    threads: usize,
+    by_rule: bool,
+    by_type: bool,
+    by_extension: Option<ExtensionFormat>,
+    tree: bool,
+    format: OutputFormat,
+    /// Where the results go, see `output::Output`. None is stdout.
+    output: Option<PathBuf>,
+    /// See --columns.
+    columns: Vec<csv::Column>,
+    /// Where to write a snapshot of the scan, see `snapshot::save`.
+    save: Option<PathBuf>,
+    /// See --folded-prefix.
+    folded_prefix: bool,
+    /// Collect every entry instead of the sizes of groups, see `Aggregate::entries`.
+    keep_entries: bool,
+    /// Fold entries smaller than this many bytes together, see `threshold::fold_small_entries`.
+    threshold: Option<u64>,
+    /// How to order the entries. None keeps the path order `du` uses.
+    sort: Option<Sort>,
+    /// See `print_top`. Never set together with `web`.
+    top: Option<Top>,
+    /// The file types for --by-type and the browser.
+    types: Option<Types>,
+    /// Group sizes by git work tree, see `print_repos`.
+    repos: bool,
+    repo_column: RepoColumn,
+    count_links: bool,
+    one_file_system: bool,
+    breakdown: bool,
+    /// Tell tracked and untracked files apart. This reads the index of every git repository.
+    git_status: bool,
 }
 
 impl Config {
@@ -170,6 +494,59 @@ impl Config {
         let direct_size = matches.get_flag("direct-size");
         let open = matches.get_flag("open");
         let web = matches.get_flag("web");
+        let threads = matches.get_one::<usize>("threads").copied().unwrap_or(1);
+        let by_rule = matches.get_flag("by-rule");
+        let repo_column = matches.get_one::<RepoColumn>("repos").copied();
+        let by_type = matches.get_flag("by-type");
+        let by_extension = matches.get_one::<ExtensionFormat>("by-extension").copied();
+        let threshold = matches.get_one::<u64>("threshold").copied();
+        let tree = matches.get_flag("tree");
+        let format = matches.get_one::<OutputFormat>("format").copied().unwrap_or(OutputFormat::Text);
+        // --format defaults to text, so clap can't tell `--format text` from no --format at all.
+        if format != OutputFormat::Text {
+            let given = REPORTS
+                .into_iter()
+                .chain(["web", "open"])
+                .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+            if let Some(id) = given {
+                let format = format.to_possible_value().unwrap();
+                let message = format!("the argument '--format {}' cannot be used with '--{}'\n", format.get_name(), id);
+                clap::Error::raw(ErrorKind::ArgumentConflict, message).exit();
+            }
+        }
+        let output = matches.get_one::<PathBuf>("output").cloned();
+        let folded_prefix = matches.get_flag("folded-prefix");
+        let save = matches.get_one::<PathBuf>("save").cloned();
+        let columns: Vec<csv::Column> = match matches.get_many::<csv::Column>("columns") {
+            Some(columns) => columns.copied().collect(),
+            None => csv::Column::DEFAULT.to_vec(),
+        };
+        let reverse = matches.get_flag("reverse");
+        let flat = matches.get_flag("flat");
+        let sort = match matches.get_one::<SortKey>("sort").copied() {
+            Some(key) => Some(Sort { key, reverse, flat }),
+            None if reverse || flat => Some(Sort { key: SortKey::Name, reverse, flat }),
+            None => None,
+        };
+        let top = [
+            ("top", TopKind::All),
+            ("top-files", TopKind::Files),
+            ("top-dirs", TopKind::Directories),
+        ]
+        .into_iter()
+        .find_map(|(id, kind)| matches.get_one::<usize>(id).map(|&count| Top { count, kind }))
+        // The browser needs the whole tree.
+        .filter(|_| !(web || open));
+        let types = if by_type || web || open {
+            Some(file_types(&matches))
+        } else {
+            None
+        };
+        let count_links = matches.get_flag("count-links");
+        let one_file_system = matches.get_flag("one-file-system");
+        let breakdown = matches.get_flag("breakdown");
+        let globs = Globs::parse(&matches);
+        let omit_excluded = matches.get_one::<ExcludeMode>("exclude-mode") == Some(&ExcludeMode::Omitted);
 
         let mode: Mode = matches
             .get_one::<Mode>("mode")
@@ -189,6 +566,8 @@ impl Config {
 
         Self {
             include_hidden,
+            globs,
+            omit_excluded,
             max_depth,
             human_readable,
             use_apparent_size,
@@ -197,15 +576,204 @@ impl Config {
             show_only_directories,
             web: web || open,
             open,
+            threads,
+            by_rule,
+            by_type,
+            by_extension,
+            threshold,
+            tree,
+            format,
+            output,
+            columns,
+            save,
+            folded_prefix,
+            keep_entries: format == OutputFormat::Ncdu && !(web || open),
+            sort,
+            top,
+            types,
+            repos: repo_column.is_some(),
+            repo_column: repo_column.unwrap_or(RepoColumn::Total),
+            count_links,
+            one_file_system,
+            breakdown,
+            git_status: breakdown || web || open || folded_prefix || matches!(mode, Mode::Tracked | Mode::Untracked),
+        }
+    }
+}
+
+/// The built-in file types, plus the ones added with --type-add.
+fn file_types(matches: &clap::ArgMatches) -> Types {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    let added: Vec<&String> = matches.get_many::<String>("type-add").unwrap_or_default().collect();
+    for def in &added {
+        if let Err(err) = builder.add_def(def) {
+            eprintln!("--type-add {}: {}", def, err);
+            std::process::exit(1);
+        }
+    }
+    // When several selected types match a file, the one selected last wins. Select in reverse
+    // alphabetical order, so that e.g. `*.h` counts as `c` rather than `objcpp`, and the added
+    // types last so they win over the built-in ones.
+    let mut names: Vec<String> = builder.definitions().iter().map(|def| def.name().to_owned()).collect();
+    names.reverse();
+    for name in names {
+        builder.select(&name);
+    }
+    for def in added {
+        builder.select(def.split(':').next().unwrap_or_default());
+    }
+    match builder.build() {
+        Ok(types) => types,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// See --top.
+#[derive(Copy, Clone)]
+struct Top {
+    count: usize,
+    kind: TopKind,
+}
+
+#[derive(Copy, PartialEq, Eq, Clone)]
+enum TopKind {
+    All,
+    Files,
+    Directories,
+}
+
+/// See --by-extension.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+enum ExtensionFormat {
+    Table,
+    Json,
+}
+
+/// See --format.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Ncdu,
+    Folded,
+}
+
+/// See --exclude-mode.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+enum ExcludeMode {
+    Ignored,
+    Omitted,
+}
+
+/// The --include and --exclude globs. They become overrides of the walker, so they take
+/// precedence over every ignore file.
+#[derive(Default)]
+struct Globs {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+/// The arguments parsed by `Globs::parse`. `duh why` takes them too.
+fn glob_args() -> [Arg; 3] {
+    [
+        Arg::new("exclude")
+            .long("exclude")
+            .value_name("GLOB")
+            .action(ArgAction::Append)
+            .help("Exclude paths matching GLOB, using .gitignore syntax relative to the scanned path. Can be given more than once. Excluding a directory excludes everything in it. Takes precedence over every ignore file."),
+        Arg::new("exclude-from")
+            .long("exclude-from")
+            .value_name("FILE")
+            .action(ArgAction::Append)
+            .help("Exclude paths matching any glob in FILE, one per line. Blank lines and lines starting with # are skipped."),
+        Arg::new("include")
+            .long("include")
+            .value_name("GLOB")
+            .action(ArgAction::Append)
+            .help("Only include files matching GLOB. Can be given more than once. Directories are always walked. Like ripgrep's --glob, an included file counts as not ignored even when a .gitignore ignores it, unless its directory is ignored or excluded."),
+    ]
+}
+
+impl Globs {
+    fn parse(matches: &clap::ArgMatches) -> Self {
+        let strings = |id: &str| -> Vec<String> { matches.get_many::<String>(id).unwrap_or_default().cloned().collect() };
+        let mut exclude = strings("exclude");
+        for file in strings("exclude-from") {
+            match std::fs::read_to_string(&file) {
+                Ok(contents) => exclude.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(str::to_owned),
+                ),
+                Err(err) => {
+                    eprintln!("{}: {}", file, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Self {
+            include: strings("include"),
+            exclude,
+        }
+    }
+
+    /// Add the globs to `builder` as overrides relative to `path`.
+    fn apply(&self, path: &str, builder: &mut WalkBuilder) {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return;
+        }
+        let mut overrides = OverrideBuilder::new(path);
+        let globs = self
+            .include
+            .iter()
+            .cloned()
+            // An override that starts with ! ignores what it matches.
+            .chain(self.exclude.iter().map(|glob| format!("!{}", glob)));
+        for glob in globs {
+            if let Err(err) = overrides.add(&glob) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        match overrides.build() {
+            Ok(overrides) => {
+                builder.overrides(overrides);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
         }
     }
 }
 
+/// The column `--repos` sorts by. Sizes sort biggest first, paths alphabetically.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+enum RepoColumn {
+    Total,
+    Ignored,
+    NotIgnored,
+    Git,
+    Path,
+}
+
 #[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
 enum Mode {
     Du,
     Ignored,
     NotIgnored,
+    Tracked,
+    Untracked,
+    GitMetadata,
 }
 
 impl Mode {
@@ -214,6 +782,9 @@ impl Mode {
             Mode::Du => "all files (both ignored and not ignored)",
             Mode::Ignored => "only files that are ignored by .gitignore rules",
             Mode::NotIgnored => "only files that are NOT ignored by .gitignore rules",
+            Mode::Tracked => "only files that are in the index of a git repository",
+            Mode::Untracked => "only files in a git repository that are neither ignored nor in its index",
+            Mode::GitMetadata => "only .git directories",
         }
     }
 
@@ -222,28 +793,52 @@ impl Mode {
             Mode::Du => "du",
             Mode::Ignored => "ignored",
             Mode::NotIgnored => "not-ignored",
+            Mode::Tracked => "tracked",
+            Mode::Untracked => "untracked",
+            Mode::GitMetadata => "git-metadata",
         }
     }
 }
 
-fn process_directory(path: &str, config: &Config) {
+/// The walker with duh's ignore rules. `duh why` uses this too, so that it explains exactly
+/// what a scan would do.
+fn walk_builder(path: &str, include_hidden: bool, globs: &Globs) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(path);
+    globs.apply(path, &mut builder);
+    builder.hidden(include_hidden).filter_entry(|entry| {
+        // It seems that .git directories are not automatically ignored. Weird. The scanner counts
+        // everything rejected here as git metadata, so only ever reject `.git`.
+        entry.file_name().to_str().map(|s| { s != ".git"}).unwrap_or(true)
+    });
+    builder
+}
+
+fn process_directory(path: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let aggregate = scan_directory(path, config);
+    if let Some(file) = &config.save {
+        snapshot::save(file, &aggregate, path, config);
+    }
+    print_results(aggregate, path, config, out)
+}
+
+/// Walk `path` with a progress bar on stderr, and add up what was found.
+fn scan_directory(path: &str, config: &Config) -> scan::Aggregate {
     // Explain what mode is being used
     eprintln!("Mode: '{}' - analyzing {}", config.mode.name(), config.mode.description());
     match config.mode {
         Mode::Du => (),
         Mode::Ignored => eprintln!("Note: Progress bar shows ALL files visited, but results will only include ignored files.\n"),
         Mode::NotIgnored => eprintln!("Note: Progress bar shows ALL files visited, but results will only include not ignored files.\n"),
+        Mode::Tracked => eprintln!("Note: Progress bar shows ALL files visited, but results will only include tracked files.\n"),
+        Mode::Untracked => eprintln!("Note: Progress bar shows ALL files visited, but results will only include untracked files.\n"),
+        Mode::GitMetadata => eprintln!("Note: Progress bar shows ALL files visited, but results will only include files in .git directories.\n"),
     }
 
-    let walker = WalkBuilder::new(path).hidden(config.include_hidden).filter_entry(|entry| {
-        // It seems that .git directories are not automatically ignored. Weird.
-        entry.file_name().to_str().map(|s| { s != ".git"}).unwrap_or(true)
-    }).build();
-
-    let mut groups: IndexMap<PathBuf, (u64, u64, bool)> = IndexMap::new();
-    // We also to keep track of the largest value in this hashmap so we can format the output We
-    // will print the size of each
-    let mut max_size: u64 = 0;
+    let mut builder = walk_builder(path, config.include_hidden, &config.globs);
+    builder.same_file_system(config.one_file_system);
+    if config.threads > 1 {
+        builder.threads(config.threads);
+    }
 
     //let starting_absolute_path = std::env::current_dir()
     //    .unwrap()
@@ -258,229 +853,563 @@ fn process_directory(path: &str, config: &Config) {
         .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
     pb.set_style(spinner_style);
     pb.set_message("Scanning files... (0 files)");
+    let progress = scan::Progress::new(pb);
 
-    // Throttling mechanism - update at most 10 times per second
-    let mut file_count = 0u64;
-    let mut ignored_count = 0u64; // Count of ignored files (for du mode breakdown)
-    let mut not_ignored_count = 0u64; // Count of not ignored files (for du mode breakdown)
-    let mut last_update = Instant::now();
-    let update_interval = Duration::from_millis(100); // 100ms = 10 times per second
-
-    for result in walker {
-        match result {
-            Err(_) => continue,
-            Ok(dent) => {
-                // Update file count and progress bar (throttled)
-                file_count += 1;
-                
-                // Track ignored vs not-ignored counts for du mode breakdown
-                if dent.ignored {
-                    ignored_count += 1;
-                } else {
-                    not_ignored_count += 1;
-                }
-                
-                let now = Instant::now();
-                if now.duration_since(last_update) >= update_interval {
-                    pb.set_message(format!("Scanning files... ({} files)", format_number(file_count)));
-                    pb.tick();
-                    last_update = now;
-                }
-
-                #[cfg(unix)]
-                use std::os::unix::fs::MetadataExt;
-                let size = {
-                    #[cfg(unix)]
-                    match dent.metadata() {
-                        Err(_) => continue,
-                        Ok(metadata) => {
-                            if config.use_apparent_size {
-                                metadata.len()
-                            } else {
-                                metadata.blocks() * 512
-                            }
-                        }
-                    }
-                    #[cfg(not(unix))]
-                    match dent.metadata() {
-                        Err(_) => continue,
-                        Ok(metadata) => metadata.len(),
-                    }
-                };
-
-                // I want to take up to max_depth components from the directory portion of a
-                // filepath, but not include the filename.
-                struct FileComponentIter<'a> {
-                    iter: std::iter::Peekable<std::path::Iter<'a>>,
-                    current_depth: u64,
-                    max_depth: Option<u64>,
-                }
-
-                impl<'a> Iterator for FileComponentIter<'a> {
-                    type Item = &'a std::ffi::OsStr;
-
-                    fn next(&mut self) -> Option<Self::Item> {
-                        if self.iter.peek().is_none() || Some(self.current_depth) == self.max_depth
-                        {
-                            None
-                        } else {
-                            self.current_depth += 1;
-                            self.iter.next()
-                        }
-                    }
-                }
-
-                match dent.file_type() {
-                    None => (),
-                    Some(ft) => {
-                        if let Ok(relative_path) = dent.path().strip_prefix(path) {
-                            let should_process = config.mode == Mode::Du
-                                || (config.mode == Mode::Ignored && dent.ignored)
-                                || (config.mode == Mode::NotIgnored && !dent.ignored);
-
-                            if !should_process {
-                                continue;
-                            }
-
-                            let it: Box<dyn Iterator<Item = &'_ std::ffi::OsStr>> = if ft.is_file()
-                            {
-                                Box::new(FileComponentIter {
-                                    iter: relative_path.iter().peekable(),
-                                    current_depth: 0,
-                                    max_depth: config.max_depth,
-                                })
-                            } else {
-                                if let Some(max_depth) = config.max_depth {
-                                    Box::new(relative_path.iter().take(max_depth as usize))
-                                } else {
-                                    Box::new(relative_path.iter())
-                                }
-                            };
-                            let mut key = PathBuf::new();
-                            for component in it {
-                                if config.use_recursive_size {
-                                    if let Some((ref mut not_ignored_acc, ref mut ignored_acc, _)) =
-                                        groups.get_mut(&key)
-                                    {
-                                        if dent.ignored {
-                                            *ignored_acc += size;
-                                        } else {
-                                            *not_ignored_acc += size;
-                                        }
-                                        max_size = std::cmp::max(
-                                            max_size,
-                                            *ignored_acc + *not_ignored_acc,
-                                        );
-                                    } else {
-                                        let (ref mut not_ignored_acc, ref mut ignored_acc, _) =
-                                            groups.entry(key.clone()).or_insert((
-                                                0,
-                                                0,
-                                                ft.is_file(),
-                                            ));
-                                        if dent.ignored {
-                                            *ignored_acc += size;
-                                        } else {
-                                            *not_ignored_acc += size;
-                                        }
-                                        max_size = std::cmp::max(
-                                            max_size,
-                                            *ignored_acc + *not_ignored_acc,
-                                        );
-                                    }
-                                }
-                                key.push(component);
-                            }
-                            let (ref mut not_ignored_acc, ref mut ignored_acc, _) =
-                                groups.entry(key.clone()).or_insert((0, 0, ft.is_file()));
-                            if dent.ignored {
-                                *ignored_acc += size;
-                            } else {
-                                *not_ignored_acc += size;
-                            }
-                            max_size = std::cmp::max(max_size, *ignored_acc + *not_ignored_acc);
-                        } else {
-                            eprintln!("failed to strip prefix")
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let aggregate = if config.threads > 1 {
+        scan::parallel(&builder, path, config, &progress)
+    } else {
+        scan::sequential(&builder, path, config, &progress)
+    };
 
     // Finish progress bar with final file count
     let final_message = match config.mode {
+        Mode::Du if config.git_status => {
+            format!("Visited {} files, {} ignored, {} not ignored ({} tracked, {} untracked), {} in .git",
+                format_number(aggregate.file_count),
+                format_number(aggregate.ignored_count),
+                format_number(aggregate.not_ignored_count),
+                format_number(aggregate.tracked_count),
+                format_number(aggregate.untracked_count),
+                format_number(aggregate.git_metadata_count)
+            )
+        },
         Mode::Du => {
-            format!("Visited {} files, {} ignored, {} not ignored", 
-                format_number(file_count),
-                format_number(ignored_count),
-                format_number(not_ignored_count)
+            format!("Visited {} files, {} ignored, {} not ignored, {} in .git",
+                format_number(aggregate.file_count),
+                format_number(aggregate.ignored_count),
+                format_number(aggregate.not_ignored_count),
+                format_number(aggregate.git_metadata_count)
             )
         },
         Mode::Ignored => {
             format!("Visited {} files, {} ignored", 
-                format_number(file_count), 
-                format_number(ignored_count)
+                format_number(aggregate.file_count), 
+                format_number(aggregate.ignored_count)
             )
         },
         Mode::NotIgnored => {
             format!("Visited {} files, {} not ignored", 
-                format_number(file_count), 
-                format_number(not_ignored_count)
+                format_number(aggregate.file_count), 
+                format_number(aggregate.not_ignored_count)
+            )
+        }
+        Mode::Tracked => {
+            format!("Visited {} files, {} tracked",
+                format_number(aggregate.file_count),
+                format_number(aggregate.tracked_count)
+            )
+        }
+        Mode::Untracked => {
+            format!("Visited {} files, {} untracked",
+                format_number(aggregate.file_count),
+                format_number(aggregate.untracked_count)
+            )
+        }
+        Mode::GitMetadata => {
+            format!("Visited {} files, {} in .git",
+                format_number(aggregate.file_count),
+                format_number(aggregate.git_metadata_count)
             )
         }
     };
-    pb.finish_with_message(final_message);
+    progress.bar().finish_with_message(final_message);
+    aggregate
+}
+
+/// Print what was found below `path`, in the format selected by the options.
+fn print_results(aggregate: scan::Aggregate, path: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    // We also to keep track of the largest value in this hashmap so we can format the output We
+    // will print the size of each
+    let max_size = aggregate.max_size(config.mode);
+    if matches!(config.mode, Mode::Du | Mode::GitMetadata) {
+        // A status note like the mode, so it never ends up in a report, a pipe or JSON.
+        print_git_metadata(&aggregate.git_metadata, config);
+    }
+    mounts::print_skipped(&aggregate.mounts, path);
+
+    if config.by_rule && !config.web {
+        return print_rules(aggregate.rules, config, out);
+    }
+
+    if config.by_type && !config.web {
+        return print_types(aggregate.types, config, out);
+    }
+
+    if let Some(format) = config.by_extension.filter(|_| !config.web) {
+        return print_extensions(aggregate.extensions, format, path, config, out);
+    }
+
+    if let Some(top) = config.top {
+        return print_top(aggregate, top, path, config, out);
+    }
+
+    if config.repos && !config.web {
+        return print_repos(aggregate.repos, path, config, out);
+    }
 
-    let mut pairs: Vec<(PathBuf, (u64, u64, bool))> = groups.into_iter().collect();
+    if config.keep_entries {
+        return ncdu::write(aggregate.entries, path, out);
+    }
+
+    let mut pairs: Vec<(PathBuf, Group)> = aggregate.groups.into_iter().collect();
+
+    if let Some(threshold) = config.threshold {
+        if !config.web && config.format != OutputFormat::Folded && config.show_only_directories {
+            // Files that aren't listed anyway shouldn't show up as smaller entries.
+            pairs.retain(|(_, group)| !group.is_file);
+        }
+        pairs = threshold::fold_small_entries(pairs, threshold, config.mode, config.use_recursive_size);
+    }
 
     if config.web {
-        self::visualize::view_in_browser(&pairs, config.open);
-        return;
+        // visualize::encode expects every directory to be immediately followed by its
+        // children. The walk order used to guarantee that, but threads finish in any order.
+        pairs.sort_by(|a, b| compare_components(&a.0, &b.0, Ordering::Greater));
+        self::visualize::view_in_browser(&pairs, config.types.as_ref(), None, config.open);
+        return Ok(());
     }
 
-    pairs.sort_by(|a, b| {
-        let mut xs = a.0.iter();
-        let mut ys = b.0.iter();
-        use std::cmp::Ordering;
-        loop {
-            match (xs.next(), ys.next()) {
-                (Some(x), Some(y)) => match x.cmp(&y) {
-                    Ordering::Equal => continue,
-                    non_eq => return non_eq,
-                },
-                (Some(_), None) => return Ordering::Less,
-                (None, Some(_)) => return Ordering::Greater,
-                (None, None) => return Ordering::Equal,
-            }
+    if config.format == OutputFormat::Folded {
+        return folded::write(&pairs, path, config, out);
+    }
+
+    if config.tree {
+        if config.show_only_directories {
+            pairs.retain(|(_, group)| !group.is_file);
         }
-    });
+        return tree::print(pairs, path, config, out);
+    }
+
+    // Like du, print the contents of a directory before the directory itself.
+    match config.sort {
+        Some(sort) => pairs = sort.apply(pairs, config.mode, false),
+        None => pairs.sort_by(|a, b| compare_components(&a.0, &b.0, Ordering::Less)),
+    }
+
+    if config.format != OutputFormat::Text {
+        if config.show_only_directories {
+            pairs.retain(|(_, group)| !group.is_file);
+        }
+        return match config.format {
+            OutputFormat::Csv | OutputFormat::Tsv => csv::write(&pairs, path, config, out),
+            _ => json::write(&pairs, path, config, out),
+        };
+    }
+
+    let first_column_width = (max_size / 512).to_string().len();
+    let breakdown_columns = ["total", "ignored", "tracked", "untracked", "git"];
+    // Human readable sizes are always 5 characters wide.
+    let column_width = |name: &str| {
+        let width = if config.human_readable { 5 } else { first_column_width };
+        width.max(name.len())
+    };
+    if config.breakdown {
+        let header: Vec<String> = breakdown_columns
+            .iter()
+            .map(|name| format!("{:>width$}", name, width = column_width(name)))
+            .collect();
+        writeln!(out, "{} path", header.join(" "))?;
+    }
 
-    for (suffix, (not_ignored_size, ignored_size, is_file)) in pairs {
-        if config.show_only_directories && is_file {
+    for (suffix, group) in pairs {
+        if config.show_only_directories && group.is_file {
             continue;
         }
 
-        let size = match config.mode {
-            Mode::Du => not_ignored_size + ignored_size,
-            Mode::Ignored => ignored_size,
-            Mode::NotIgnored => not_ignored_size,
-        };
+        let size = group.size(config.mode);
 
-        if config.human_readable {
-            eprintln!(
+        if config.breakdown {
+            let sizes = [group.total(), group.ignored, group.tracked, group.untracked, group.git_metadata];
+            let columns: Vec<String> = sizes
+                .iter()
+                .zip(breakdown_columns)
+                .map(|(size, name)| {
+                    let width = column_width(name);
+                    if config.human_readable {
+                        format!("{:>width$}", format_human_readable(*size), width = width)
+                    } else {
+                        format!("{:>width$}", size / 512, width = width)
+                    }
+                })
+                .collect();
+            writeln!(out, "{} {}", columns.join(" "), Path::new(path).join(suffix).display())?;
+        } else if config.human_readable {
+            writeln!(
+                out,
                 "{} {}",
                 format_human_readable(size),
                 Path::new(path).join(suffix).display()
-            );
+            )?;
         } else {
-            let first_column_width = (max_size / 512).to_string().len();
-            eprintln!(
+            writeln!(
+                out,
                 "{:>first_column_width$} {}",
                 size / 512,
                 Path::new(path).join(suffix).display(),
                 first_column_width = first_column_width,
-            );
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Summarize what the space in `.git` directories is used for.
+fn print_git_metadata(git: &GitMetadata, config: &Config) {
+    if git.total() == 0 {
+        return;
+    }
+    let format_size = |size: u64| {
+        if config.human_readable {
+            format_human_readable(size).trim_start().to_owned()
+        } else {
+            (size / 512).to_string()
+        }
+    };
+    eprintln!(
+        "Git metadata: {} (packs {}, loose objects {}, lfs {}, worktrees {}, other {})",
+        format_size(git.total()),
+        format_size(git.packs),
+        format_size(git.loose_objects),
+        format_size(git.lfs),
+        format_size(git.worktrees),
+        format_size(git.other),
+    );
+}
+
+/// Print one line per ignore rule, biggest first.
+fn print_rules(rules: HashMap<IgnoreReason, (u64, u64)>, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut rules: Vec<(String, (u64, u64))> = rules
+        .into_iter()
+        .map(|(reason, totals)| (describe_ignore_reason(&reason), totals))
+        .collect();
+    rules.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+
+    let max_size = rules.first().map(|(_, (bytes, _))| *bytes).unwrap_or(0);
+    for (description, (size, count)) in rules {
+        let count = format!("({} entries)", format_number(count));
+        if config.human_readable {
+            writeln!(out, "{} {} {}", format_human_readable(size), description, count)?;
+        } else {
+            let first_column_width = (max_size / 512).to_string().len();
+            writeln!(
+                out,
+                "{:>first_column_width$} {} {}",
+                size / 512,
+                description,
+                count,
+                first_column_width = first_column_width,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Print one line per file type, biggest first.
+fn print_types(types: HashMap<String, TypeSizes>, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut rows: Vec<(String, TypeSizes)> = types.into_iter().collect();
+    rows.sort_by(|a, b| b.1.group.total().cmp(&a.1.group.total()).then_with(|| a.0.cmp(&b.0)));
+
+    let max_size = rows.first().map(|(_, sizes)| sizes.group.total()).unwrap_or(0);
+    let first_column_width = (max_size / 512).to_string().len();
+    // Human readable sizes are always 5 characters wide.
+    let size_width = if config.human_readable { 5 } else { first_column_width };
+    let columns = ["total", "ignored", "not-ignored"];
+    let header: Vec<String> = columns
+        .iter()
+        .map(|name| format!("{:>width$}", name, width = size_width.max(name.len())))
+        .collect();
+    writeln!(out, "{} type", header.join(" "))?;
+
+    for (name, sizes) in rows {
+        let group = sizes.group;
+        let cells: Vec<String> = [group.total(), group.ignored, group.not_ignored]
+            .iter()
+            .zip(columns)
+            .map(|(size, name)| {
+                let width = size_width.max(name.len());
+                if config.human_readable {
+                    format!("{:>width$}", format_human_readable(*size), width = width)
+                } else {
+                    format!("{:>width$}", size / 512, width = width)
+                }
+            })
+            .collect();
+        let name = if name.is_empty() { "(no type)" } else { name.as_str() };
+        writeln!(
+            out,
+            "{} {} ({} files, {} ignored)",
+            cells.join(" "),
+            name,
+            format_number(sizes.not_ignored_files + sizes.ignored_files),
+            format_number(sizes.ignored_files),
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the biggest files and directories, biggest first.
+///
+/// The files were already narrowed down to `top.count` during the walk, see `TopFiles`. The
+/// directories are all still there, since their sizes were only final after the walk.
+fn print_top(aggregate: scan::Aggregate, top: Top, root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut rows = aggregate.top_files.into_vec();
+    // Files are only in `groups` when the whole tree was kept, for --save or `duh open`.
+    let rest = aggregate.groups.into_iter().filter(|(path, group)| {
+        if group.is_file {
+            top.kind != TopKind::Directories
+        } else {
+            // The root is always the biggest directory, and would only ever say 100%.
+            top.kind != TopKind::Files && !path.as_os_str().is_empty()
+        }
+    });
+    rows.extend(rest);
+    rows.sort_by(|a, b| {
+        b.1.size(config.mode)
+            .cmp(&a.1.size(config.mode))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    rows.truncate(top.count);
+
+    let max_size = rows.first().map(|(_, group)| group.size(config.mode)).unwrap_or(0);
+    let first_column_width = (max_size / 512).to_string().len();
+    // Human readable sizes are always 5 characters wide.
+    let size_width = if config.human_readable { 5 } else { first_column_width };
+    let format_size = |size: u64, name: &str| {
+        let width = size_width.max(name.len());
+        if config.human_readable {
+            format!("{:>width$}", format_human_readable(size), width = width)
+        } else {
+            format!("{:>width$}", size / 512, width = width)
+        }
+    };
+    let width = |name: &str| size_width.max(name.len());
+    writeln!(
+        out,
+        "{:>size$} {:>6} {:>ignored$} {:>not_ignored$} {:>git$} path",
+        "size",
+        "%",
+        "ignored",
+        "not-ignored",
+        "git",
+        size = width("size"),
+        ignored = width("ignored"),
+        not_ignored = width("not-ignored"),
+        git = width("git"),
+    )?;
+    for (path, group) in rows {
+        let size = group.size(config.mode);
+        let percent = if aggregate.mode_total == 0 {
+            0.0
+        } else {
+            100.0 * size as f64 / aggregate.mode_total as f64
+        };
+        writeln!(
+            out,
+            "{} {:>5.1}% {} {} {} {}",
+            format_size(size, "size"),
+            percent,
+            format_size(group.ignored, "ignored"),
+            format_size(group.not_ignored, "not-ignored"),
+            format_size(group.git_metadata, "git"),
+            Path::new(root).join(path).display(),
+        )?;
+    }
+    Ok(())
+}
+
+/// One extension in the output of --by-extension=json.
+#[derive(Serialize)]
+struct ExtensionRecord {
+    extension: String,
+    bytes: u64,
+    ignored_bytes: u64,
+    not_ignored_bytes: u64,
+    files: u64,
+    mean_bytes: u64,
+    largest_path: Option<String>,
+    largest_bytes: Option<u64>,
+}
+
+/// Print one line per file extension, biggest first.
+fn print_extensions(extensions: HashMap<String, ExtensionSizes>, format: ExtensionFormat, root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut rows: Vec<(String, ExtensionSizes)> = extensions.into_iter().collect();
+    rows.sort_by(|a, b| b.1.group.total().cmp(&a.1.group.total()).then_with(|| a.0.cmp(&b.0)));
+
+    if format == ExtensionFormat::Json {
+        let records: Vec<ExtensionRecord> = rows
+            .into_iter()
+            .map(|(extension, sizes)| ExtensionRecord {
+                mean_bytes: sizes.mean(),
+                bytes: sizes.group.total(),
+                ignored_bytes: sizes.group.ignored,
+                not_ignored_bytes: sizes.group.not_ignored,
+                files: sizes.files,
+                largest_path: sizes
+                    .largest
+                    .as_ref()
+                    .map(|(_, path)| Path::new(root).join(path).to_string_lossy().into_owned()),
+                largest_bytes: sizes.largest.map(|(size, _)| size),
+                extension,
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &records)?;
+        return writeln!(out);
+    }
+
+    let max_size = rows.first().map(|(_, sizes)| sizes.group.total()).unwrap_or(0);
+    let first_column_width = (max_size / 512).to_string().len();
+    let format_size = |size: u64, width: usize| {
+        if config.human_readable {
+            format!("{:>width$}", format_human_readable(size), width = width)
+        } else {
+            format!("{:>width$}", size / 512, width = width)
+        }
+    };
+    // Human readable sizes are always 5 characters wide.
+    let size_width = if config.human_readable { 5 } else { first_column_width };
+    let columns = ["total", "ignored", "not-ignored", "mean"];
+    let header: Vec<String> = columns
+        .iter()
+        .map(|name| format!("{:>width$}", name, width = size_width.max(name.len())))
+        .collect();
+    writeln!(out, "{} extension", header.join(" "))?;
+
+    for (extension, sizes) in rows {
+        let group = &sizes.group;
+        let cells: Vec<String> = [group.total(), group.ignored, group.not_ignored, sizes.mean()]
+            .iter()
+            .zip(columns)
+            .map(|(size, name)| format_size(*size, size_width.max(name.len())))
+            .collect();
+        let extension = if extension.is_empty() {
+            "(no extension)".to_owned()
+        } else {
+            format!(".{}", extension)
+        };
+        let largest = match &sizes.largest {
+            Some((size, path)) => format!(
+                ", largest {} {}",
+                format_size(*size, 0).trim_start(),
+                Path::new(root).join(path).display()
+            ),
+            None => String::new(),
+        };
+        writeln!(
+            out,
+            "{} {} ({} files{})",
+            cells.join(" "),
+            extension,
+            format_number(sizes.files),
+            largest,
+        )?;
+    }
+    Ok(())
+}
+
+/// Print one line per git work tree found under `root`.
+fn print_repos(repos: HashMap<PathBuf, (Option<Arc<git::Repository>>, Group)>, root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    let mut rows: Vec<(String, &'static str, Group)> = repos
+        .into_values()
+        .map(|(repository, group)| match repository {
+            Some(repository) => (
+                repository.display_path(root).display().to_string(),
+                repository.kind.name(),
+                group,
+            ),
+            None => ("(not in a repository)".to_owned(), "-", group),
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        let order = match config.repo_column {
+            RepoColumn::Total => b.2.total().cmp(&a.2.total()),
+            RepoColumn::Ignored => b.2.ignored.cmp(&a.2.ignored),
+            RepoColumn::NotIgnored => b.2.not_ignored.cmp(&a.2.not_ignored),
+            RepoColumn::Git => b.2.git_metadata.cmp(&a.2.git_metadata),
+            RepoColumn::Path => Ordering::Equal,
+        };
+        order.then_with(|| a.0.cmp(&b.0))
+    });
+
+    let max_size = rows.iter().map(|(_, _, group)| group.total()).max().unwrap_or(0);
+    let first_column_width = (max_size / 512).to_string().len();
+    // Human readable sizes are always 5 characters wide.
+    let size_width = if config.human_readable { 5 } else { first_column_width };
+    let kind_width = rows.iter().map(|(_, kind, _)| kind.len()).max().unwrap_or(0).max("kind".len());
+    // With --breakdown the index was read, so tracked and untracked sizes are known too.
+    let columns: &[&str] = if config.git_status {
+        &["total", "ignored", "not-ignored", "tracked", "untracked", "git"]
+    } else {
+        &["total", "ignored", "not-ignored", "git"]
+    };
+    let header: Vec<String> = columns
+        .iter()
+        .map(|name| format!("{:>width$}", name, width = size_width.max(name.len())))
+        .collect();
+    writeln!(out, "{} {:<kind_width$} path", header.join(" "), "kind", kind_width = kind_width)?;
+
+    for (path, kind, group) in rows {
+        let sizes = if config.git_status {
+            vec![group.total(), group.ignored, group.not_ignored, group.tracked, group.untracked, group.git_metadata]
+        } else {
+            vec![group.total(), group.ignored, group.not_ignored, group.git_metadata]
+        };
+        let cells: Vec<String> = sizes
+            .iter()
+            .zip(columns)
+            .map(|(size, name)| {
+                let width = size_width.max(name.len());
+                if config.human_readable {
+                    format!("{:>width$}", format_human_readable(*size), width = width)
+                } else {
+                    format!("{:>width$}", size / 512, width = width)
+                }
+            })
+            .collect();
+        writeln!(out, "{} {:<kind_width$} {}", cells.join(" "), kind, path, kind_width = kind_width)?;
+    }
+    Ok(())
+}
+
+/// Describe an ignore rule in a way that tells you where to go to change it.
+fn describe_ignore_reason(reason: &IgnoreReason) -> String {
+    match (reason.kind(), reason.pattern()) {
+        (IgnoreKind::Gitignore, pattern) => {
+            let pattern = pattern.unwrap_or_default();
+            match (reason.from(), reason.line()) {
+                (Some(from), Some(line)) => format!("`{}` in {}:{}", pattern, from.display(), line),
+                (Some(from), None) => format!("`{}` in {}", pattern, from.display()),
+                (None, _) => format!("`{}`", pattern),
+            }
+        }
+        // Overrides only ever come from --include and --exclude, see `Globs`.
+        (IgnoreKind::Override, Some(pattern)) => match pattern.strip_prefix('!') {
+            Some(glob) => format!("--exclude `{}`", glob),
+            None => format!("--include `{}`", pattern),
+        },
+        (IgnoreKind::Override, None) => "not matching any --include".to_owned(),
+        (IgnoreKind::Types, Some(name)) => format!("file type `{}`", name),
+        (IgnoreKind::Types, None) => "not matched by any selected file type".to_owned(),
+        (IgnoreKind::Hidden, _) => "hidden file or directory".to_owned(),
+        (IgnoreKind::Filter, _) => "`.git` directory".to_owned(),
+        (IgnoreKind::MaxFilesize, _) => "larger than the maximum file size".to_owned(),
+        (IgnoreKind::Stdout, _) => "same file as stdout".to_owned(),
+    }
+}
+
+/// Compare two paths component by component. When one path is a prefix of the other,
+/// the longer path is ordered `longer` relative to the shorter one.
+fn compare_components(a: &Path, b: &Path, longer: Ordering) -> Ordering {
+    let mut xs = a.iter();
+    let mut ys = b.iter();
+    loop {
+        match (xs.next(), ys.next()) {
+            (Some(x), Some(y)) => match x.cmp(y) {
+                Ordering::Equal => continue,
+                non_eq => return non_eq,
+            },
+            (Some(_), None) => return longer,
+            (None, Some(_)) => return longer.reverse(),
+            (None, None) => return Ordering::Equal,
         }
     }
 }
@@ -499,3 +1428,83 @@ fn format_human_readable(size: u64) -> String {
     }
     format!("{:4.0}{}", size, sizes[i])
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A temporary directory that is deleted when it's dropped, like the one in the tests of
+    /// patched_ignore, in lieu of tempfile.
+    #[derive(Debug)]
+    pub struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).unwrap();
+        }
+    }
+
+    impl TempDir {
+        pub fn new() -> TempDir {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+            let tmpdir = env::temp_dir().join("duh-tests").join(std::process::id().to_string());
+            loop {
+                let path = tmpdir.join(COUNTER.fetch_add(1, Ordering::SeqCst).to_string());
+                if path.is_dir() {
+                    continue;
+                }
+                fs::create_dir_all(&path).unwrap();
+                return TempDir(path);
+            }
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Write `contents` to `relative_path`, creating the directories it is in.
+        pub fn write(&self, relative_path: &str, contents: &[u8]) {
+            let path = self.0.join(relative_path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn reports_conflict() {
+        let parse = |args: &[&str]| super::command().try_get_matches_from([&["duh"], args].concat());
+        for report in ["--by-rule", "--by-type", "--by-extension", "--top=3", "--repos", "--tree"] {
+            let err = parse(&[report, "--web"]).err().unwrap();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict, "{} --web", report);
+        }
+        assert!(parse(&["--tree", "--top-dirs", "3"]).is_err());
+        assert!(parse(&["--top", "3", "--top-files", "3"]).is_err());
+        assert!(parse(&["--web", "--open"]).is_ok());
+        assert!(parse(&["--format", "text", "--tree"]).is_ok());
+    }
+
+    #[test]
+    fn file_type_precedence() {
+        let types = |args: &[&str]| super::file_types(&super::command().get_matches_from([&["duh"], args].concat()));
+        let file_type = |types: &super::Types, path: &str| super::scan::file_type(types, Path::new(path)).map(str::to_owned);
+
+        let built_in = types(&[]);
+        // `*.h` is a header of c, cpp, objc and objcpp, the first in alphabetical order wins.
+        assert_eq!(file_type(&built_in, "a.h").as_deref(), Some("c"));
+        assert_eq!(file_type(&built_in, "a.rs").as_deref(), Some("rust"));
+        assert_eq!(file_type(&built_in, "a.unknown"), None);
+
+        // Added types win over the built-in ones, and of those the last one given wins.
+        let added = types(&["--type-add", "mine:*.rs", "--type-add", "headers:*.h", "--type-add", "other:*.h"]);
+        assert_eq!(file_type(&added, "a.rs").as_deref(), Some("mine"));
+        assert_eq!(file_type(&added, "a.h").as_deref(), Some("other"));
+        // Adding a glob to a built-in type keeps its name.
+        let extended = types(&["--type-add", "rust:*.rsx"]);
+        assert_eq!(file_type(&extended, "a.rsx").as_deref(), Some("rust"));
+    }
+}
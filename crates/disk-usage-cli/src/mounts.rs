@@ -0,0 +1,217 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::{Path, PathBuf};
+
+/// A directory that --one-file-system did not descend into because it is on another file
+/// system.
+pub struct MountPoint {
+    /// Path relative to the root being scanned.
+    pub path: PathBuf,
+    pub device: u64,
+}
+
+/// List the mount points skipped while scanning `root`.
+pub fn print_skipped(mounts: &[MountPoint], root: &str) {
+    if mounts.is_empty() {
+        return;
+    }
+    let table = mount_table();
+    eprintln!(
+        "Skipped {} mount point{} on other file systems:",
+        mounts.len(),
+        if mounts.len() == 1 { "" } else { "s" }
+    );
+    for mount in mounts {
+        let path = Path::new(root).join(&mount.path);
+        let fs_type = path
+            .canonicalize()
+            .ok()
+            .and_then(|absolute| find_fs_type(&table, &absolute, mount.device));
+        match fs_type {
+            Some(fs_type) => eprintln!(
+                "    {} (device {}, {})",
+                path.display(),
+                format_device(mount.device),
+                fs_type
+            ),
+            None => eprintln!("    {} (device {})", path.display(), format_device(mount.device)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn format_device(device: u64) -> String {
+    // The encoding used by glibc's major() and minor(), which is also how mountinfo prints them.
+    let major = ((device >> 8) & 0xfff) | ((device >> 32) & 0xffff_f000);
+    let minor = (device & 0xff) | ((device >> 12) & 0xffff_ff00);
+    format!("{}:{}", major, minor)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn format_device(device: u64) -> String {
+    device.to_string()
+}
+
+/// One line of /proc/self/mountinfo.
+struct MountEntry {
+    device: String,
+    mount_point: PathBuf,
+    fs_type: String,
+}
+
+#[cfg(target_os = "linux")]
+fn mount_table() -> Vec<MountEntry> {
+    match std::fs::read("/proc/self/mountinfo") {
+        Ok(contents) => contents.split(|&b| b == b'\n').filter_map(parse_mountinfo_line).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_table() -> Vec<MountEntry> {
+    Vec::new()
+}
+
+/// Parse a line like
+/// `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue`.
+/// See proc(5) for the details.
+#[cfg(target_os = "linux")]
+fn parse_mountinfo_line(line: &[u8]) -> Option<MountEntry> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut fields = line.split(|&b| b == b' ');
+    let device = fields.nth(2)?;
+    let mount_point = fields.nth(1)?;
+    // Skip the optional fields, which are terminated by a single hyphen.
+    let fs_type = fields.find(|field| *field == b"-").and_then(|_| fields.next())?;
+    Some(MountEntry {
+        device: String::from_utf8_lossy(device).into_owned(),
+        mount_point: PathBuf::from(OsString::from_vec(unescape_octal(mount_point))),
+        fs_type: String::from_utf8_lossy(fs_type).into_owned(),
+    })
+}
+
+/// Mount points in mountinfo have spaces, tabs, newlines and backslashes escaped as `\ooo`.
+#[cfg(target_os = "linux")]
+fn unescape_octal(field: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let escaped = field.get(i + 1..i + 4).filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)));
+        match (field[i], escaped) {
+            (b'\\', Some(digits)) => {
+                out.push(digits.iter().fold(0u8, |acc, d| acc.wrapping_mul(8) + (d - b'0')));
+                i += 4;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn find_fs_type(table: &[MountEntry], absolute: &Path, device: u64) -> Option<String> {
+    // Later mounts hide earlier ones on the same mount point, so search from the end.
+    let device = format_device(device);
+    table
+        .iter()
+        .rev()
+        .find(|entry| entry.mount_point == absolute)
+        .or_else(|| table.iter().rev().find(|entry| entry.device == device))
+        .map(|entry| entry.fs_type.clone())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{find_fs_type, format_device, parse_mountinfo_line, unescape_octal};
+    use std::path::Path;
+
+    /// glibc's makedev().
+    fn makedev(major: u64, minor: u64) -> u64 {
+        ((major & 0xfff) << 8) | ((major & !0xfff) << 32) | (minor & 0xff) | ((minor & !0xff) << 12)
+    }
+
+    #[test]
+    fn device() {
+        assert_eq!(format_device(makedev(8, 1)), "8:1");
+        assert_eq!(format_device(makedev(259, 300)), "259:300");
+        assert_eq!(format_device(makedev(4100, 1 << 20)), "4100:1048576");
+        assert_eq!(format_device(0), "0:0");
+    }
+
+    #[test]
+    fn optional_fields() {
+        let line = b"36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 shared:7 - ext3 /dev/root rw,errors=continue";
+        let entry = parse_mountinfo_line(line).unwrap();
+        assert_eq!(entry.device, "98:0");
+        assert_eq!(entry.mount_point, Path::new("/mnt/parent"));
+        assert_eq!(entry.fs_type, "ext3");
+
+        let line = b"22 1 0:21 / /proc rw,nosuid - proc proc rw";
+        let entry = parse_mountinfo_line(line).unwrap();
+        assert_eq!(entry.mount_point, Path::new("/proc"));
+        assert_eq!(entry.fs_type, "proc");
+    }
+
+    #[test]
+    fn escaped_mount_point() {
+        let line = b"97 29 0:45 / /media/USB\\040Drive\\011x rw - vfat /dev/sdb1 rw";
+        let entry = parse_mountinfo_line(line).unwrap();
+        assert_eq!(entry.mount_point, Path::new("/media/USB Drive\tx"));
+        assert_eq!(entry.fs_type, "vfat");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert!(parse_mountinfo_line(b"").is_none());
+        assert!(parse_mountinfo_line(b"36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1").is_none());
+        assert!(parse_mountinfo_line(b"36 35 98:0 /mnt1 /mnt/parent rw -").is_none());
+    }
+
+    #[test]
+    fn unescape() {
+        assert_eq!(unescape_octal(b"a\\040b"), b"a b");
+        assert_eq!(unescape_octal(b"back\\134slash"), b"back\\slash");
+        assert_eq!(unescape_octal(b"new\\012line"), b"new\nline");
+        // Not an escape: too short or not octal digits.
+        assert_eq!(unescape_octal(b"a\\04"), b"a\\04");
+        assert_eq!(unescape_octal(b"a\\089"), b"a\\089");
+        assert_eq!(unescape_octal(b"trailing\\"), b"trailing\\");
+    }
+
+    #[test]
+    fn fs_type_by_mount_point_then_device() {
+        let table: Vec<_> = [
+            &b"1 0 8:1 / / rw - ext4 /dev/sda1 rw"[..],
+            b"2 1 0:50 / /mnt/data rw - tmpfs tmpfs rw",
+            b"3 1 0:51 / /mnt/data rw - overlay overlay rw",
+            b"4 1 259:300 / /srv rw - xfs /dev/nvme0n1p1 rw",
+        ]
+        .into_iter()
+        .filter_map(parse_mountinfo_line)
+        .collect();
+        // The later mount hides the earlier one.
+        assert_eq!(find_fs_type(&table, Path::new("/mnt/data"), 0).as_deref(), Some("overlay"));
+        // Reached through a bind mount or symlink, so only the device matches.
+        assert_eq!(
+            find_fs_type(&table, Path::new("/elsewhere"), makedev(259, 300)).as_deref(),
+            Some("xfs")
+        );
+        assert_eq!(find_fs_type(&table, Path::new("/elsewhere"), makedev(9, 9)), None);
+    }
+}
@@ -0,0 +1,229 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::Output;
+use crate::scan::{ScannedEntry, Status};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAJOR_VERSION: u64 = 1;
+const MINOR_VERSION: u64 = 2;
+
+#[derive(Serialize)]
+struct Metadata {
+    progname: &'static str,
+    progver: &'static str,
+    timestamp: u64,
+}
+
+/// The info object of one entry. ncdu skips the keys it doesn't know, so the `duh_` keys
+/// don't get in the way of browsing the export in ncdu.
+#[derive(Serialize)]
+struct Info<'a> {
+    name: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dsize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<u64>,
+    /// Neither a regular file nor a directory, like a symlink.
+    #[serde(skip_serializing_if = "is_false")]
+    notreg: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    duh_ignored: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    duh_git_metadata: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl<'a> Info<'a> {
+    fn new(name: Cow<'a, str>, entry: &ScannedEntry) -> Self {
+        Info {
+            name,
+            asize: Some(entry.apparent_size),
+            dsize: Some(entry.disk_size),
+            mtime: Some(entry.mtime),
+            notreg: !entry.is_file && !entry.is_dir,
+            duh_ignored: entry.status == Status::Ignored,
+            duh_git_metadata: entry.status == Status::GitMetadata,
+        }
+    }
+
+    /// A directory that wasn't counted in the current mode, but has contents that were.
+    fn directory(name: Cow<'a, str>) -> Self {
+        Info {
+            name,
+            asize: None,
+            dsize: None,
+            mtime: None,
+            notreg: false,
+            duh_ignored: false,
+            duh_git_metadata: false,
+        }
+    }
+}
+
+/// Write `entries`, found below `root`, to `out` in ncdu's export format, see
+/// https://dev.yorhel.nl/ncdu/jsonfmt.
+///
+/// A directory is an array of its own info object followed by its contents, anything else is
+/// just an info object. Sizes are those of the entry itself, ncdu adds them up on import.
+pub fn write(mut entries: Vec<ScannedEntry>, root: &str, out: &mut Output) -> io::Result<()> {
+    // Path order puts every directory right before everything below it.
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut entries = entries.into_iter().peekable();
+
+    let metadata = Metadata {
+        progname: "duh",
+        progver: env!("CARGO_PKG_VERSION"),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs()),
+    };
+    write!(out, "[{},{},", MAJOR_VERSION, MINOR_VERSION)?;
+    serde_json::to_writer(&mut *out, &metadata)?;
+
+    // ncdu shows the name of the root as the path that was scanned, so make it absolute.
+    let root_name = match Path::new(root).canonicalize() {
+        Ok(absolute) => Cow::Owned(absolute.to_string_lossy().into_owned()),
+        Err(_) => Cow::Borrowed(root),
+    };
+    let root_info = match entries.next_if(|entry| entry.path.as_os_str().is_empty()) {
+        Some(entry) => Info::new(root_name, &entry),
+        None => Info::directory(root_name),
+    };
+    out.write_all(b",\n[")?;
+    serde_json::to_writer(&mut *out, &root_info)?;
+
+    // The directories whose arrays are still open, innermost last.
+    let mut open: Vec<PathBuf> = vec![PathBuf::new()];
+    for entry in entries {
+        let parent = entry.path.parent().unwrap_or(Path::new(""));
+        while !parent.starts_with(open.last().unwrap()) {
+            out.write_all(b"]")?;
+            open.pop();
+        }
+        let missing = parent.strip_prefix(open.last().unwrap()).unwrap().to_path_buf();
+        for component in missing.iter() {
+            out.write_all(b",\n[")?;
+            serde_json::to_writer(&mut *out, &Info::directory(component.to_string_lossy()))?;
+            let directory = open.last().unwrap().join(component);
+            open.push(directory);
+        }
+
+        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        out.write_all(if entry.is_dir { b",\n[" } else { b",\n" })?;
+        serde_json::to_writer(&mut *out, &Info::new(name, &entry))?;
+        if entry.is_dir {
+            open.push(entry.path.clone());
+        }
+    }
+    for _ in open {
+        out.write_all(b"]")?;
+    }
+    out.write_all(b"]\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use crate::output::Output;
+    use crate::scan::{ScannedEntry, Status};
+    use crate::tests::TempDir;
+    use serde_json::Value;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, is_dir: bool, status: Status) -> ScannedEntry {
+        ScannedEntry {
+            path: PathBuf::from(path),
+            is_file: !is_dir,
+            is_dir,
+            status,
+            apparent_size: 10,
+            disk_size: 4096,
+            mtime: 1,
+        }
+    }
+
+    /// The names in an exported tree, with the ignored and git metadata entries marked.
+    fn names(value: &Value) -> String {
+        match value {
+            Value::Array(items) => format!("[{}]", items.iter().map(names).collect::<Vec<_>>().join(" ")),
+            Value::Object(info) => {
+                let mut name = info["name"].as_str().unwrap().to_owned();
+                if info.get("duh_ignored") == Some(&Value::Bool(true)) {
+                    name.push('!');
+                }
+                if info.get("duh_git_metadata") == Some(&Value::Bool(true)) {
+                    name.push('@');
+                }
+                if !info.contains_key("asize") {
+                    name.push('?');
+                }
+                name
+            }
+            _ => panic!("unexpected {}", value),
+        }
+    }
+
+    fn export(entries: Vec<ScannedEntry>) -> Value {
+        let td = TempDir::new();
+        let file = td.path().join("export.json");
+        let mut out = Output::new(Some(&file));
+        write(entries, "root", &mut out).unwrap();
+        out.flush().unwrap();
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn nested_export() {
+        let entries = vec![
+            entry("z.txt", false, Status::NotIgnored),
+            entry("target/debug/duh", false, Status::Ignored),
+            entry("src/main.rs", false, Status::NotIgnored),
+            entry("src", true, Status::NotIgnored),
+            entry(".git/HEAD", false, Status::GitMetadata),
+            entry("", true, Status::NotIgnored),
+            entry("src/bin", true, Status::NotIgnored),
+        ];
+        let export = export(entries);
+        let export = export.as_array().unwrap();
+        assert_eq!(export.len(), 4);
+        assert_eq!((&export[0], &export[1]), (&Value::from(1), &Value::from(2)));
+        assert_eq!(export[2]["progname"], "duh");
+        // A root that doesn't exist here keeps its name as it was given.
+        assert_eq!(export[3][0]["name"], "root");
+        assert_eq!((&export[3][0]["asize"], &export[3][0]["dsize"]), (&Value::from(10), &Value::from(4096)));
+        assert!(export[3][0].get("notreg").is_none());
+        // Directories that weren't counted themselves are there without sizes.
+        assert_eq!(
+            names(&export[3]),
+            "[root [.git? HEAD@] [src [bin] main.rs] [target? [debug? duh!]] z.txt]"
+        );
+    }
+
+    #[test]
+    fn root_not_counted() {
+        let export = export(vec![entry("build/out", false, Status::Ignored)]);
+        assert_eq!(names(&export[3]), "[root? [build? out!]]");
+    }
+}
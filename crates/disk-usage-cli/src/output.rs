@@ -0,0 +1,75 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Where the results go: stdout, or the file given with --output.
+///
+/// Every format writes its results here. Progress, the mode, warnings and errors go to stderr,
+/// so they never end up in a pipe or a report.
+pub struct Output {
+    writer: BufWriter<Box<dyn Write>>,
+    to_stdout: bool,
+}
+
+impl Output {
+    pub fn new(path: Option<&Path>) -> Self {
+        let writer: Box<dyn Write> = match path {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    eprintln!("{}: {}", path.display(), err);
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(io::stdout()),
+        };
+        Output {
+            writer: BufWriter::new(writer),
+            to_stdout: path.is_none(),
+        }
+    }
+
+    /// The width of the terminal the results are shown on, or None when they aren't.
+    pub fn terminal_width(&self) -> Option<usize> {
+        if !self.to_stdout {
+            return None;
+        }
+        console::Term::stdout()
+            .size_checked()
+            .map(|(_, columns)| columns as usize)
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Stop because the results couldn't be written. When the reader went away, like `head` in
+/// `duh | head`, there is nobody left to tell, so that exits quietly.
+pub fn exit_on_error(err: io::Error) -> ! {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        std::process::exit(0);
+    }
+    eprintln!("Failed to write the results: {}", err);
+    std::process::exit(1);
+}
@@ -0,0 +1,1198 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::mounts::MountPoint;
+use crate::git::{Repositories, Repository};
+use crate::{format_number, Config, Mode, TopKind};
+use ignore::{
+    DirEntry, IgnoreKind, IgnoreReason, ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState,
+};
+use ignore::types::{FileTypeDef, Types};
+use indexmap::IndexMap;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Which bucket the bytes of an entry go to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ignored,
+    /// Inside a `.git` directory, or a `.git` file.
+    GitMetadata,
+    /// Not ignored, and in the index of the repository that contains it.
+    Tracked,
+    /// Not ignored, in a repository, but not in its index.
+    Untracked,
+    /// Not ignored, and either not inside any git repository or we didn't check.
+    NotIgnored,
+}
+
+/// Sizes of everything at or below one path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub not_ignored: u64,
+    pub ignored: u64,
+    pub git_metadata: u64,
+    /// The part of `not_ignored` that is in the index of a git repository.
+    pub tracked: u64,
+    /// The part of `not_ignored` that is in a git repository but not in its index. Files outside
+    /// of any repository are neither tracked nor untracked.
+    pub untracked: u64,
+    /// Number of files at or below this path.
+    pub count: u64,
+    /// The newest modification time at or below this path, in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// The apparent size of the entries counted here, whether or not --apparent is given.
+    pub apparent_size: u64,
+    /// The disk usage of the entries counted here, whether or not --apparent is given.
+    pub disk_size: u64,
+    pub is_file: bool,
+}
+
+impl Group {
+    fn new(is_file: bool) -> Self {
+        Self {
+            is_file,
+            ..Self::default()
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.not_ignored + self.ignored + self.git_metadata
+    }
+
+    /// The size that counts in the given mode.
+    pub fn size(&self, mode: Mode) -> u64 {
+        match mode {
+            Mode::Du => self.total(),
+            Mode::Ignored => self.ignored,
+            Mode::NotIgnored => self.not_ignored,
+            Mode::Tracked => self.tracked,
+            Mode::Untracked => self.untracked,
+            Mode::GitMetadata => self.git_metadata,
+        }
+    }
+
+    fn add(&mut self, status: Status, size: u64) {
+        match status {
+            Status::Ignored => self.ignored += size,
+            Status::GitMetadata => self.git_metadata += size,
+            Status::NotIgnored => self.not_ignored += size,
+            Status::Tracked => {
+                self.not_ignored += size;
+                self.tracked += size;
+            }
+            Status::Untracked => {
+                self.not_ignored += size;
+                self.untracked += size;
+            }
+        }
+    }
+
+    /// Like `add`, but also count the entry, its modification time and both of its sizes.
+    fn add_entry(&mut self, entry: &SizedEntry) {
+        self.add(entry.status, entry.size);
+        self.count += u64::from(entry.is_file);
+        self.mtime = self.mtime.max(entry.mtime);
+        self.apparent_size += entry.apparent_size;
+        self.disk_size += entry.disk_size;
+    }
+
+    pub fn merge(&mut self, other: &Group) {
+        self.count += other.count;
+        self.mtime = self.mtime.max(other.mtime);
+        self.apparent_size += other.apparent_size;
+        self.disk_size += other.disk_size;
+        self.not_ignored += other.not_ignored;
+        self.ignored += other.ignored;
+        self.git_metadata += other.git_metadata;
+        self.tracked += other.tracked;
+        self.untracked += other.untracked;
+    }
+}
+
+/// The name of the file type `path` belongs to. See `file_types` for which one wins when
+/// several types match.
+pub fn file_type<'t>(types: &'t Types, path: &Path) -> Option<&'t str> {
+    types
+        .matched(path, false)
+        .inner()
+        .and_then(|glob| glob.file_type_def())
+        .map(FileTypeDef::name)
+}
+
+/// Sizes and file counts of the files of one type.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TypeSizes {
+    pub group: Group,
+    pub ignored_files: u64,
+    pub not_ignored_files: u64,
+}
+
+impl TypeSizes {
+    fn add(&mut self, status: Status, size: u64) {
+        self.group.add(status, size);
+        if status == Status::Ignored {
+            self.ignored_files += 1;
+        } else {
+            self.not_ignored_files += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &TypeSizes) {
+        self.group.merge(&other.group);
+        self.ignored_files += other.ignored_files;
+        self.not_ignored_files += other.not_ignored_files;
+    }
+}
+
+/// Sizes of the files with one extension.
+#[derive(Clone, Debug, Default)]
+pub struct ExtensionSizes {
+    pub group: Group,
+    pub files: u64,
+    /// The biggest file, relative to the root, and its size. Of several equally big files the
+    /// first in path order wins, so the result doesn't depend on the order of the walk.
+    pub largest: Option<(u64, PathBuf)>,
+}
+
+impl ExtensionSizes {
+    pub fn mean(&self) -> u64 {
+        self.group.total().checked_div(self.files).unwrap_or(0)
+    }
+
+    fn add(&mut self, relative_path: &Path, status: Status, size: u64) {
+        self.group.add(status, size);
+        self.files += 1;
+        if self.is_largest(size, relative_path) {
+            self.largest = Some((size, relative_path.to_path_buf()));
+        }
+    }
+
+    fn is_largest(&self, size: u64, relative_path: &Path) -> bool {
+        match &self.largest {
+            None => true,
+            Some((largest, path)) => (size, Reverse(relative_path)) > (*largest, Reverse(path.as_path())),
+        }
+    }
+
+    fn merge(&mut self, other: ExtensionSizes) {
+        self.group.merge(&other.group);
+        self.files += other.files;
+        if let Some((size, path)) = other.largest {
+            if self.is_largest(size, &path) {
+                self.largest = Some((size, path));
+            }
+        }
+    }
+}
+
+/// Bytes inside `.git` directories, split by what they are used for.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct GitMetadata {
+    /// `objects/pack`
+    pub packs: u64,
+    /// `objects/xx/...`
+    pub loose_objects: u64,
+    /// `lfs`, where git-lfs keeps its objects.
+    pub lfs: u64,
+    /// `worktrees`, the private state of each linked worktree.
+    pub worktrees: u64,
+    /// Everything else: refs, hooks, the index, logs...
+    pub other: u64,
+}
+
+impl GitMetadata {
+    pub fn total(&self) -> u64 {
+        self.packs + self.loose_objects + self.lfs + self.worktrees + self.other
+    }
+
+    fn add(&mut self, relative_path: &Path, size: u64) {
+        let mut components = relative_path.iter().skip_while(|component| *component != ".git").skip(1);
+        let mut first = components.next();
+        // Submodules keep their git directory in `.git/modules/<name>`, with the same layout.
+        while first.is_some_and(|component| component == "modules") {
+            first = components.nth(1);
+        }
+        let bucket = match (first.and_then(|c| c.to_str()), components.next().and_then(|c| c.to_str())) {
+            (Some("objects"), Some("pack")) => &mut self.packs,
+            (Some("objects"), Some(dir)) if dir.len() == 2 && dir.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                &mut self.loose_objects
+            }
+            (Some("lfs"), _) => &mut self.lfs,
+            (Some("worktrees"), _) => &mut self.worktrees,
+            _ => &mut self.other,
+        };
+        *bucket += size;
+    }
+
+    fn merge(&mut self, other: &GitMetadata) {
+        self.packs += other.packs;
+        self.loose_objects += other.loose_objects;
+        self.lfs += other.lfs;
+        self.worktrees += other.worktrees;
+        self.other += other.other;
+    }
+}
+
+/// Everything we learned from walking one root path.
+#[derive(Default)]
+pub struct Aggregate {
+    /// Size of each group, keyed by the path relative to the root.
+    pub groups: IndexMap<PathBuf, Group>,
+    /// Bytes and number of entries ignored by each rule. Only filled in with --by-rule.
+    pub rules: HashMap<IgnoreReason, (u64, u64)>,
+    pub git_metadata: GitMetadata,
+    /// Sizes of each git work tree, keyed by its canonical path. Entries that are not in any
+    /// work tree are under the empty path. Only filled in with --repos.
+    pub repos: HashMap<PathBuf, (Option<Arc<Repository>>, Group)>,
+    /// Sizes of the files of each file type, keyed by its name. Files that don't match any type
+    /// are under the empty name. Only filled in with --by-type.
+    pub types: HashMap<String, TypeSizes>,
+    /// Sizes of the files with each extension, lower cased and without the dot. Files without
+    /// an extension are under the empty string. Only filled in with --by-extension.
+    pub extensions: HashMap<String, ExtensionSizes>,
+    /// The biggest files. With --top, files go here instead of `groups`.
+    pub top_files: TopFiles,
+    /// Every entry counted in the current mode, in no particular order. With --format ncdu,
+    /// entries go here instead of `groups`.
+    pub entries: Vec<ScannedEntry>,
+    /// The size of everything counted in the current mode.
+    pub mode_total: u64,
+    pub file_count: u64,
+    pub ignored_count: u64,
+    pub not_ignored_count: u64,
+    pub git_metadata_count: u64,
+    pub tracked_count: u64,
+    pub untracked_count: u64,
+    /// Files with more than one hard link, keyed by (device, inode). They are only added to
+    /// `groups` by `finish`, once every link has been seen.
+    links: HashMap<(u64, u64), Link>,
+    /// Directories on another file system that were not descended into. Only filled in with
+    /// --one-file-system.
+    pub mounts: Vec<MountPoint>,
+}
+
+impl Aggregate {
+    /// Fold a partial aggregate (for example the one built by another thread) into this one.
+    ///
+    /// Every group key is a pure function of the entry path and the config, so merging
+    /// is just adding up the sizes. The result is the same no matter which thread saw
+    /// which entry.
+    pub fn merge(&mut self, other: Aggregate) {
+        if self.groups.is_empty() {
+            self.groups = other.groups;
+        } else {
+            for (key, group) in other.groups {
+                self.groups.entry(key).or_insert(Group::new(group.is_file)).merge(&group);
+            }
+        }
+        for (reason, (bytes, count)) in other.rules {
+            let entry = self.rules.entry(reason).or_insert((0, 0));
+            entry.0 += bytes;
+            entry.1 += count;
+        }
+        for (key, link) in other.links {
+            self.add_link(key, link);
+        }
+        self.mounts.extend(other.mounts);
+        self.file_count += other.file_count;
+        self.ignored_count += other.ignored_count;
+        self.not_ignored_count += other.not_ignored_count;
+        self.git_metadata_count += other.git_metadata_count;
+        self.git_metadata.merge(&other.git_metadata);
+        self.top_files.merge(other.top_files);
+        self.entries.extend(other.entries);
+        self.mode_total += other.mode_total;
+        for (extension, sizes) in other.extensions {
+            self.extensions.entry(extension).or_default().merge(sizes);
+        }
+        for (name, sizes) in other.types {
+            self.types.entry(name).or_default().merge(&sizes);
+        }
+        for (work_tree, (repository, group)) in other.repos {
+            self.repos
+                .entry(work_tree)
+                .or_insert((repository, Group::new(false)))
+                .1
+                .merge(&group);
+        }
+        self.tracked_count += other.tracked_count;
+        self.untracked_count += other.untracked_count;
+    }
+
+    /// The largest size of any group in `mode`. Used to size the first output column.
+    pub fn max_size(&self, mode: Mode) -> u64 {
+        self.groups
+            .values()
+            .map(|group| group.size(mode))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn add(&mut self, dent: &DirEntry, root: &Root, config: &Config) {
+        let metadata = dent.metadata();
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+        #[cfg(unix)]
+        if let (Some(root_device), Ok(metadata)) = (root.device, &metadata) {
+            // The walker still yields the mount point, it just doesn't descend into it. Like
+            // `du -x`, leave it out of the totals entirely.
+            if dent.depth() > 0 && metadata.is_dir() && metadata.dev() != root_device {
+                if let Ok(path) = dent.path().strip_prefix(root.path) {
+                    self.mounts.push(MountPoint {
+                        path: path.to_path_buf(),
+                        device: metadata.dev(),
+                    });
+                }
+                return;
+            }
+        }
+
+        if config.omit_excluded && dent.ignore_reason().map(IgnoreReason::kind) == Some(IgnoreKind::Override) {
+            return;
+        }
+
+        self.file_count += 1;
+
+        // Anything in `.git` is git metadata, even when the walker reached it as a hidden file or
+        // inside an ignored directory. The filter given to the walker only rejects `.git`, see
+        // `walk_builder`.
+        let git_metadata = dent.path().components().any(|component| component.as_os_str() == ".git")
+            || dent.ignore_reason().map(IgnoreReason::kind) == Some(IgnoreKind::Filter);
+
+        // Track ignored vs not-ignored counts for du mode breakdown
+        if git_metadata {
+            self.git_metadata_count += 1;
+        } else if dent.ignored {
+            self.ignored_count += 1;
+        } else {
+            self.not_ignored_count += 1;
+        }
+
+        let metadata = match metadata {
+            Err(_) => return,
+            Ok(metadata) => metadata,
+        };
+        let apparent_size = metadata.len();
+        #[cfg(unix)]
+        let disk_size = metadata.blocks() * 512;
+        #[cfg(not(unix))]
+        let disk_size = apparent_size;
+        let size = if config.use_apparent_size { apparent_size } else { disk_size };
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+
+        let ft = match dent.file_type() {
+            None => return,
+            Some(ft) => ft,
+        };
+
+        let relative_path = match dent.path().strip_prefix(root.path) {
+            Ok(relative_path) => relative_path,
+            Err(_) => {
+                eprintln!("failed to strip prefix");
+                return;
+            }
+        };
+
+        let status = if git_metadata {
+            Status::GitMetadata
+        } else if dent.ignored {
+            Status::Ignored
+        } else if let Some(repositories) = root.repositories.filter(|_| config.git_status) {
+            repositories.status(relative_path, ft.is_dir())
+        } else {
+            Status::NotIgnored
+        };
+        match status {
+            Status::Tracked => self.tracked_count += 1,
+            Status::Untracked => self.untracked_count += 1,
+            Status::Ignored | Status::GitMetadata | Status::NotIgnored => (),
+        }
+
+        let repository = match root.repositories.filter(|_| config.repos) {
+            Some(repositories) if ft.is_dir() => repositories.repository(relative_path),
+            Some(repositories) => repositories.repository(relative_path.parent().unwrap_or(Path::new(""))),
+            None => None,
+        };
+
+        #[cfg(unix)]
+        if !config.count_links && !ft.is_dir() && metadata.nlink() > 1 {
+            let link = Link {
+                path: relative_path.to_path_buf(),
+                is_file: ft.is_file(),
+                status,
+                reason: dent.ignore_reason().filter(|_| config.by_rule).cloned(),
+                repository,
+                size,
+                apparent_size,
+                disk_size,
+                mtime,
+            };
+            self.add_link((metadata.dev(), metadata.ino()), link);
+            return;
+        }
+
+        let entry = SizedEntry {
+            path: relative_path,
+            is_file: ft.is_file(),
+            is_dir: ft.is_dir(),
+            status,
+            reason: dent.ignore_reason(),
+            repository: repository.as_ref(),
+            size,
+            apparent_size,
+            disk_size,
+            mtime,
+        };
+        self.add_size(entry, config);
+    }
+
+    fn add_link(&mut self, key: (u64, u64), link: Link) {
+        match self.links.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(link);
+            }
+            Entry::Occupied(mut entry) => {
+                if link.counts_before(entry.get()) {
+                    entry.insert(link);
+                }
+            }
+        }
+    }
+
+    /// Count every hard linked file seen by `add` exactly once.
+    ///
+    /// Call this after the last `merge`, so that the links seen by every thread are considered.
+    pub fn finish(&mut self, config: &Config) {
+        self.mounts.sort_by(|a, b| a.path.cmp(&b.path));
+        for (_, link) in std::mem::take(&mut self.links) {
+            let entry = SizedEntry {
+                path: &link.path,
+                is_file: link.is_file,
+                is_dir: false,
+                status: link.status,
+                reason: link.reason.as_ref(),
+                repository: link.repository.as_ref(),
+                size: link.size,
+                apparent_size: link.apparent_size,
+                disk_size: link.disk_size,
+                mtime: link.mtime,
+            };
+            self.add_size(entry, config);
+        }
+        if let Some(top) = config.top {
+            self.top_files.truncate(top.count);
+        }
+    }
+
+    fn add_size(&mut self, entry: SizedEntry, config: &Config) {
+        let SizedEntry {
+            path: relative_path,
+            is_file,
+            status,
+            reason,
+            repository,
+            size,
+            ..
+        } = entry;
+
+        // `.git` isn't left out by a rule of the user, it has a summary of its own.
+        if config.by_rule && status != Status::GitMetadata {
+            if let Some(reason) = reason {
+                // Avoid cloning the reason for every file below an ignored directory.
+                match self.rules.get_mut(reason) {
+                    Some((bytes, count)) => {
+                        *bytes += size;
+                        *count += 1;
+                    }
+                    None => {
+                        self.rules.insert(reason.clone(), (size, 1));
+                    }
+                }
+            }
+        }
+
+        if status == Status::GitMetadata {
+            self.git_metadata.add(relative_path, size);
+        }
+
+        if let Some(types) = config.types.as_ref().filter(|_| config.by_type && is_file) {
+            // Everything in .git would just be noise here, it has its own summary.
+            if status != Status::GitMetadata {
+                let name = file_type(types, relative_path).unwrap_or("");
+                // Avoid allocating the name for every file.
+                match self.types.get_mut(name) {
+                    Some(sizes) => sizes.add(status, size),
+                    None => {
+                        let mut sizes = TypeSizes::default();
+                        sizes.add(status, size);
+                        self.types.insert(name.to_owned(), sizes);
+                    }
+                }
+            }
+        }
+
+        if config.by_extension.is_some() && is_file && status != Status::GitMetadata {
+            let extension = relative_path.extension().map(OsStr::to_string_lossy).unwrap_or_default();
+            let extension = if extension.chars().any(char::is_uppercase) {
+                Cow::Owned(extension.to_lowercase())
+            } else {
+                extension
+            };
+            // Avoid allocating the extension for every file.
+            match self.extensions.get_mut(extension.as_ref()) {
+                Some(sizes) => sizes.add(relative_path, status, size),
+                None => {
+                    let mut sizes = ExtensionSizes::default();
+                    sizes.add(relative_path, status, size);
+                    self.extensions.insert(extension.into_owned(), sizes);
+                }
+            }
+        }
+
+        if config.repos {
+            let work_tree = repository.map_or(Path::new(""), |repository| repository.work_tree.as_path());
+            // Avoid cloning the work tree for every file in it.
+            match self.repos.get_mut(work_tree) {
+                Some((_, group)) => group.add(status, size),
+                None => {
+                    let mut group = Group::new(false);
+                    group.add(status, size);
+                    self.repos.insert(work_tree.to_path_buf(), (repository.cloned(), group));
+                }
+            }
+        }
+
+        let should_process = match config.mode {
+            Mode::Du => true,
+            Mode::Ignored => status == Status::Ignored,
+            Mode::NotIgnored => matches!(status, Status::Tracked | Status::Untracked | Status::NotIgnored),
+            Mode::Tracked => status == Status::Tracked,
+            Mode::Untracked => status == Status::Untracked,
+            Mode::GitMetadata => status == Status::GitMetadata,
+        };
+
+        if !should_process {
+            return;
+        }
+        self.mode_total += size;
+
+        if config.keep_entries {
+            self.entries.push(ScannedEntry {
+                path: relative_path.to_path_buf(),
+                is_file,
+                is_dir: entry.is_dir,
+                status,
+                apparent_size: entry.apparent_size,
+                disk_size: entry.disk_size,
+                mtime: entry.mtime,
+            });
+            return;
+        }
+
+        let it: Box<dyn Iterator<Item = &'_ std::ffi::OsStr>> = if is_file {
+            Box::new(FileComponentIter {
+                iter: relative_path.iter().peekable(),
+                current_depth: 0,
+                max_depth: config.max_depth,
+            })
+        } else if let Some(max_depth) = config.max_depth {
+            Box::new(relative_path.iter().take(max_depth as usize))
+        } else {
+            Box::new(relative_path.iter())
+        };
+        let mut key = PathBuf::new();
+        for component in it {
+            if config.use_recursive_size {
+                // Every key above the entry itself is one of its parent directories.
+                self.groups
+                    .entry(key.clone())
+                    .or_insert(Group::new(false))
+                    .add_entry(&entry);
+            }
+            key.push(component);
+        }
+        // When --depth cuts the path short the key is a directory, even for a file.
+        let key_is_file = is_file && key.as_path() == relative_path;
+        // A snapshot needs every file, `print_top` picks the biggest later.
+        if let Some(top) = config.top.filter(|_| key_is_file && config.save.is_none()) {
+            if top.kind != TopKind::Directories {
+                let mut group = Group::new(true);
+                group.add_entry(&entry);
+                self.top_files.push(key, group, top.count);
+            }
+            return;
+        }
+        self.groups
+            .entry(key)
+            .or_insert(Group::new(key_is_file))
+            .add_entry(&entry);
+    }
+}
+
+/// The biggest files seen so far.
+///
+/// With --top only this many files are kept at a time, so memory doesn't grow with the number
+/// of files. Of several equally big files the first in path order is kept.
+///
+/// Directories can't be kept this way: every file below a directory adds to its size, so which
+/// directories are the biggest is only known once the walk is done. They stay in `groups`, one
+/// per directory rather than per file, and `print_top` picks the biggest of them at the end.
+#[derive(Default)]
+pub struct TopFiles {
+    /// A min-heap, so the smallest file is the one to drop.
+    heap: BinaryHeap<Reverse<TopFile>>,
+}
+
+struct TopFile {
+    path: PathBuf,
+    group: Group,
+}
+
+impl TopFile {
+    fn key(&self) -> (u64, Reverse<&Path>) {
+        // A file only counts towards the current mode, so its total is its size.
+        (self.group.total(), Reverse(self.path.as_path()))
+    }
+}
+
+impl PartialEq for TopFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for TopFile {}
+
+impl PartialOrd for TopFile {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TopFile {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl TopFiles {
+    fn push(&mut self, path: PathBuf, group: Group, limit: usize) {
+        self.heap.push(Reverse(TopFile { path, group }));
+        self.truncate(limit);
+    }
+
+    /// Keep other's files too. Call `truncate` once every thread is merged.
+    fn merge(&mut self, other: TopFiles) {
+        self.heap.extend(other.heap);
+    }
+
+    fn truncate(&mut self, limit: usize) {
+        while self.heap.len() > limit {
+            self.heap.pop();
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<(PathBuf, Group)> {
+        self.heap
+            .into_iter()
+            .map(|Reverse(file)| (file.path, file.group))
+            .collect()
+    }
+}
+
+/// One file, directory or other entry, with only its own size.
+pub struct ScannedEntry {
+    /// Relative to the root being scanned.
+    pub path: PathBuf,
+    pub is_file: bool,
+    pub is_dir: bool,
+    pub status: Status,
+    pub apparent_size: u64,
+    pub disk_size: u64,
+    /// Seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+/// An entry whose size is ready to be added to an `Aggregate`.
+struct SizedEntry<'a> {
+    /// Relative to the root being scanned.
+    path: &'a Path,
+    is_file: bool,
+    is_dir: bool,
+    status: Status,
+    reason: Option<&'a IgnoreReason>,
+    /// The work tree the entry is in. Only looked up with --repos.
+    repository: Option<&'a Arc<Repository>>,
+    /// Either `apparent_size` or `disk_size`, depending on --apparent.
+    size: u64,
+    apparent_size: u64,
+    disk_size: u64,
+    /// Seconds since the Unix epoch.
+    mtime: u64,
+}
+
+/// One link to a file that has several hard links.
+///
+/// Like `du`, a hard linked file is only counted once, under one of its links. When some links
+/// are ignored and others are not, the bytes go to a link that is not ignored, because deleting
+/// the ignored links would not free any space. Otherwise the first link in path order wins, so
+/// the result doesn't depend on the order in which threads visit the links. Links inside `.git`
+/// are treated like ignored links.
+struct Link {
+    path: PathBuf,
+    is_file: bool,
+    status: Status,
+    reason: Option<IgnoreReason>,
+    repository: Option<Arc<Repository>>,
+    size: u64,
+    apparent_size: u64,
+    disk_size: u64,
+    mtime: u64,
+}
+
+impl Link {
+    fn counts_before(&self, other: &Link) -> bool {
+        let ignored = |link: &Link| matches!(link.status, Status::Ignored | Status::GitMetadata);
+        (ignored(self), &self.path) < (ignored(other), &other.path)
+    }
+}
+
+// I want to take up to max_depth components from the directory portion of a
+// filepath, but not include the filename.
+struct FileComponentIter<'a> {
+    iter: std::iter::Peekable<std::path::Iter<'a>>,
+    current_depth: u64,
+    max_depth: Option<u64>,
+}
+
+impl<'a> Iterator for FileComponentIter<'a> {
+    type Item = &'a std::ffi::OsStr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iter.peek().is_none() || Some(self.current_depth) == self.max_depth {
+            None
+        } else {
+            self.current_depth += 1;
+            self.iter.next()
+        }
+    }
+}
+
+/// Spinner shared by every walker thread.
+pub struct Progress {
+    bar: ProgressBar,
+    visited: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(bar: ProgressBar) -> Self {
+        Self {
+            bar,
+            visited: AtomicU64::new(0),
+        }
+    }
+
+    pub fn bar(&self) -> &ProgressBar {
+        &self.bar
+    }
+
+    // Throttling mechanism - update at most 10 times per second (per thread)
+    fn tick(&self, last_update: &mut Instant) {
+        let visited = self.visited.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        if now.duration_since(*last_update) >= Duration::from_millis(100) {
+            self.bar
+                .set_message(format!("Scanning files... ({} files)", format_number(visited)));
+            self.bar.tick();
+            *last_update = now;
+        }
+    }
+}
+
+/// The path being scanned.
+#[derive(Clone, Copy)]
+struct Root<'a> {
+    path: &'a str,
+    /// The device `path` is on. Only known with --one-file-system.
+    device: Option<u64>,
+    /// Only needed to tell tracked and untracked files apart, or with --repos.
+    repositories: Option<&'a Repositories>,
+}
+
+impl<'a> Root<'a> {
+    fn new(path: &'a str, config: &Config, repositories: Option<&'a Repositories>) -> Self {
+        #[cfg(unix)]
+        let device = if config.one_file_system {
+            use std::os::unix::fs::MetadataExt;
+            std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+        } else {
+            None
+        };
+        #[cfg(not(unix))]
+        let device = None;
+        Self {
+            path,
+            device,
+            repositories,
+        }
+    }
+}
+
+fn repositories(root: &str, config: &Config) -> Option<Repositories> {
+    if config.git_status || config.repos {
+        // The index is only read when tracked and untracked files are told apart.
+        Repositories::new(root, config.git_status).ok()
+    } else {
+        None
+    }
+}
+
+/// Walk `root` on the current thread.
+pub fn sequential(builder: &WalkBuilder, root: &str, config: &Config, progress: &Progress) -> Aggregate {
+    let repositories = repositories(root, config);
+    let root = Root::new(root, config, repositories.as_ref());
+    let mut aggregate = Aggregate::default();
+    let mut last_update = Instant::now();
+    for result in builder.build() {
+        match result {
+            Err(_) => continue,
+            Ok(dent) => {
+                progress.tick(&mut last_update);
+                aggregate.add(&dent, &root, config);
+            }
+        }
+    }
+    aggregate.finish(config);
+    aggregate
+}
+
+/// Walk `root` with `WalkParallel`. Each thread builds its own partial `Aggregate`
+/// without any locking, and they are merged together when the thread finishes.
+pub fn parallel(builder: &WalkBuilder, root: &str, config: &Config, progress: &Progress) -> Aggregate {
+    let repositories = repositories(root, config);
+    let merged = Mutex::new(Aggregate::default());
+    let mut visitors = AggregateVisitorBuilder {
+        root: Root::new(root, config, repositories.as_ref()),
+        config,
+        progress,
+        merged: &merged,
+    };
+    builder.build_parallel().visit(&mut visitors);
+    let mut aggregate = merged.into_inner().unwrap();
+    aggregate.finish(config);
+    aggregate
+}
+
+struct AggregateVisitorBuilder<'s> {
+    root: Root<'s>,
+    config: &'s Config,
+    progress: &'s Progress,
+    merged: &'s Mutex<Aggregate>,
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for AggregateVisitorBuilder<'s> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(AggregateVisitor {
+            root: self.root,
+            config: self.config,
+            progress: self.progress,
+            merged: self.merged,
+            aggregate: Aggregate::default(),
+            last_update: Instant::now(),
+        })
+    }
+}
+
+struct AggregateVisitor<'s> {
+    root: Root<'s>,
+    config: &'s Config,
+    progress: &'s Progress,
+    merged: &'s Mutex<Aggregate>,
+    aggregate: Aggregate,
+    last_update: Instant,
+}
+
+impl ParallelVisitor for AggregateVisitor<'_> {
+    fn visit(&mut self, entry: Result<DirEntry, ignore::Error>) -> WalkState {
+        if let Ok(dent) = entry {
+            self.progress.tick(&mut self.last_update);
+            self.aggregate.add(&dent, &self.root, self.config);
+        }
+        WalkState::Continue
+    }
+}
+
+impl Drop for AggregateVisitor<'_> {
+    fn drop(&mut self) {
+        let aggregate = std::mem::take(&mut self.aggregate);
+        self.merged.lock().unwrap().merge(aggregate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parallel, sequential, Aggregate, ExtensionSizes, Group, Progress, Status, TopFiles};
+    use crate::tests::TempDir;
+    use crate::{command, walk_builder, Config};
+    use indicatif::ProgressBar;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    /// Scan `root` with apparent sizes. `WalkBuilder::hidden(true)` makes the walker report hidden
+    /// files as ignored.
+    fn scan(root: &Path, skip_hidden: bool) -> Aggregate {
+        scan_with(root, skip_hidden, &[])
+    }
+
+    fn scan_with(root: &Path, skip_hidden: bool, args: &[&str]) -> Aggregate {
+        let root = root.to_str().unwrap();
+        let config = Config::parse(command().get_matches_from([&["duh", "--apparent", root], args].concat()));
+        let builder = walk_builder(root, skip_hidden, &config.globs);
+        sequential(&builder, root, &config, &Progress::new(ProgressBar::hidden()))
+    }
+
+    /// The groups in path order, and the counters, of `aggregate`.
+    fn summary(aggregate: Aggregate) -> (BTreeMap<PathBuf, Group>, [u64; 7]) {
+        let counters = [
+            aggregate.mode_total,
+            aggregate.file_count,
+            aggregate.ignored_count,
+            aggregate.not_ignored_count,
+            aggregate.git_metadata_count,
+            aggregate.tracked_count,
+            aggregate.untracked_count,
+        ];
+        (aggregate.groups.into_iter().collect(), counters)
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let td = TempDir::new();
+        td.write(".gitignore", b"target/\n");
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write("target/debug/duh", b"0123456789");
+        td.write(".env", b"A=1\n");
+        td.write("src/main.rs", b"fn main() {}\n");
+        td.write("vendor/dep/.git/HEAD", b"ref: refs/heads/main\n");
+        td.write("vendor/dep/lib.rs", b"pub fn f() {}\n");
+        std::fs::hard_link(td.path().join("src/main.rs"), td.path().join("target/main.rs")).unwrap();
+
+        let root = td.path().to_str().unwrap();
+        for mode in ["du", "ignored", "not-ignored", "tracked", "untracked", "git-metadata"] {
+            let config = Config::parse(command().get_matches_from(["duh", "-a", "--mode", mode, root]));
+            let mut builder = walk_builder(root, true, &config.globs);
+            let progress = Progress::new(ProgressBar::hidden());
+            let expected = summary(sequential(&builder, root, &config, &progress));
+            builder.threads(4);
+            assert_eq!(summary(parallel(&builder, root, &config, &progress)), expected, "--mode {}", mode);
+        }
+    }
+
+    #[test]
+    fn hard_links_counted_once_where_not_ignored() {
+        let td = TempDir::new();
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write(".gitignore", b"build/\n");
+        td.write("build/copy", b"0123456789");
+        std::fs::create_dir(td.path().join("src")).unwrap();
+        std::fs::hard_link(td.path().join("build/copy"), td.path().join("src/original")).unwrap();
+
+        let build = std::fs::metadata(td.path().join("build")).unwrap().len();
+        let aggregate = scan_with(td.path(), true, &["-a"]);
+        // The ignored link comes first in path order, but the not ignored one wins the bucket.
+        assert!(!aggregate.groups.contains_key(Path::new("build/copy")));
+        assert_eq!(aggregate.groups[Path::new("src/original")].not_ignored, 10);
+        assert_eq!(aggregate.groups[Path::new("build")].ignored, build);
+
+        let aggregate = scan_with(td.path(), true, &["-a", "--count-links"]);
+        assert_eq!(aggregate.groups[Path::new("build/copy")].ignored, 10);
+        assert_eq!(aggregate.groups[Path::new("src/original")].not_ignored, 10);
+        assert_eq!(aggregate.groups[Path::new("build")].ignored, build + 10);
+    }
+
+    #[test]
+    fn exclude_counts_as_ignored_or_omits() {
+        let td = TempDir::new();
+        td.write("src/main.rs", b"fn main() {}\n");
+        td.write("data/big.csv", b"0123456789");
+        td.write("notes.log", b"1234");
+
+        let aggregate = scan_with(td.path(), true, &["-a", "--exclude", "*.csv", "--exclude", "notes.log"]);
+        assert_eq!(aggregate.groups[Path::new("data/big.csv")].ignored, 10);
+        assert_eq!(aggregate.groups[Path::new("notes.log")].ignored, 4);
+        assert_eq!(aggregate.groups[Path::new("src/main.rs")].not_ignored, 13);
+
+        let aggregate = scan_with(td.path(), true, &["-a", "--exclude", "*.csv", "--exclude-mode", "omitted"]);
+        assert!(!aggregate.groups.contains_key(Path::new("data/big.csv")));
+        assert_eq!(aggregate.groups[Path::new("data")].ignored, 0);
+        assert_eq!(aggregate.groups[Path::new("notes.log")].not_ignored, 4);
+    }
+
+    #[test]
+    fn include_overrides_gitignore() {
+        let td = TempDir::new();
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write(".gitignore", b"*.log\nbuild/\n");
+        td.write("keep.log", b"1234");
+        td.write("main.rs", b"fn main() {}\n");
+        td.write("build/out.log", b"123");
+
+        let aggregate = scan_with(td.path(), true, &["-a", "--include", "*.log"]);
+        assert_eq!(aggregate.groups[Path::new("keep.log")].not_ignored, 4);
+        // Files that don't match any --include are ignored.
+        assert_eq!(aggregate.groups[Path::new("main.rs")].ignored, 13);
+        // Inside an ignored directory, an included file stays ignored.
+        assert_eq!(aggregate.groups[Path::new("build/out.log")].ignored, 3);
+    }
+
+    #[test]
+    fn exclude_from_file() {
+        let td = TempDir::new();
+        td.write("a.tmp", b"12");
+        td.write("b.bak", b"123");
+        td.write("c.rs", b"1234");
+        td.write("excludes", b"# scratch files\n*.tmp\n\n   *.bak  \n");
+        let excludes = td.path().join("excludes");
+
+        let aggregate = scan_with(td.path(), true, &["-a", "--exclude-from", excludes.to_str().unwrap()]);
+        assert_eq!(aggregate.groups[Path::new("a.tmp")].ignored, 2);
+        assert_eq!(aggregate.groups[Path::new("b.bak")].ignored, 3);
+        assert_eq!(aggregate.groups[Path::new("c.rs")].not_ignored, 4);
+    }
+
+    #[test]
+    fn git_metadata_while_hidden_files_are_skipped() {
+        let td = TempDir::new();
+        td.write(".gitignore", b"vendor/\n");
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write("vendor/dep/.git/config", b"[core]\n");
+        td.write("vendor/dep/lib.rs", b"fn main() {}\n");
+        td.write("main.rs", b"fn main() {}\n");
+        td.write(".env", b"A=1\n");
+
+        let aggregate = scan(td.path(), true);
+        let group = |path: &str| aggregate.groups[Path::new(path)];
+        assert_eq!(group(".git/HEAD").git_metadata, 21);
+        assert_eq!(group(".git/HEAD").ignored, 0);
+        // Inside an ignored directory, `.git` is still git metadata.
+        assert_eq!(group("vendor/dep/.git/config").git_metadata, 7);
+        assert_eq!(group("vendor/dep/.git/config").ignored, 0);
+        assert_eq!(group("vendor/dep/lib.rs").ignored, 13);
+        // A hidden file that isn't in `.git` stays ignored.
+        assert_eq!(group(".env").ignored, 4);
+        assert_eq!(group("main.rs").not_ignored, 13);
+    }
+
+    #[test]
+    fn git_metadata_with_hidden_files() {
+        let td = TempDir::new();
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write(".env", b"A=1\n");
+
+        let aggregate = scan(td.path(), false);
+        let group = |path: &str| aggregate.groups[Path::new(path)];
+        assert_eq!(group(".git/HEAD").git_metadata, 21);
+        assert_eq!(group(".env").not_ignored, 4);
+    }
+
+    #[test]
+    fn largest_file_per_extension() {
+        let mut sizes = ExtensionSizes::default();
+        sizes.add(Path::new("b.rs"), Status::NotIgnored, 10);
+        sizes.add(Path::new("a.rs"), Status::Ignored, 10);
+        sizes.add(Path::new("c.rs"), Status::NotIgnored, 4);
+        // Equally big, the first in path order wins.
+        assert_eq!(sizes.largest, Some((10, PathBuf::from("a.rs"))));
+        assert_eq!(sizes.files, 3);
+        assert_eq!(sizes.mean(), 8);
+
+        let mut other = ExtensionSizes::default();
+        other.add(Path::new("0.rs"), Status::NotIgnored, 10);
+        sizes.merge(other);
+        assert_eq!(sizes.largest, Some((10, PathBuf::from("0.rs"))));
+        assert_eq!(sizes.files, 4);
+        assert_eq!(ExtensionSizes::default().mean(), 0);
+    }
+
+    #[test]
+    fn by_extension() {
+        let td = TempDir::new();
+        td.write("a.TXT", b"12345");
+        td.write("sub/b.txt", b"123");
+        td.write("Makefile", b"all:\n");
+        td.write(".git/config.txt", b"[core]\n");
+
+        let aggregate = scan_with(td.path(), false, &["--by-extension"]);
+        let txt = &aggregate.extensions["txt"];
+        assert_eq!((txt.files, txt.group.total()), (2, 8));
+        assert_eq!(txt.largest, Some((5, PathBuf::from("a.TXT"))));
+        assert_eq!(aggregate.extensions[""].files, 1);
+        assert_eq!(aggregate.extensions.len(), 2);
+    }
+
+    fn file(size: u64) -> Group {
+        Group {
+            not_ignored: size,
+            is_file: true,
+            ..Group::default()
+        }
+    }
+
+    fn top_paths(top: TopFiles) -> Vec<(String, u64)> {
+        let mut files: Vec<(String, u64)> = top
+            .into_vec()
+            .into_iter()
+            .map(|(path, group)| (path.to_str().unwrap().to_owned(), group.total()))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn top_files_keeps_the_biggest() {
+        let mut top = TopFiles::default();
+        for (path, size) in [("a", 5), ("b", 50), ("c", 1), ("d", 20), ("e", 20)] {
+            top.push(PathBuf::from(path), file(size), 2);
+        }
+        assert_eq!(top_paths(top), [("b".to_owned(), 50), ("d".to_owned(), 20)]);
+    }
+
+    #[test]
+    fn top_files_merged_from_threads() {
+        let mut top = TopFiles::default();
+        top.push(PathBuf::from("z"), file(30), 2);
+        top.push(PathBuf::from("y"), file(10), 2);
+        let mut other = TopFiles::default();
+        other.push(PathBuf::from("x"), file(30), 2);
+        other.push(PathBuf::from("w"), file(20), 2);
+        top.merge(other);
+        top.truncate(1);
+        // Equally big, the first in path order stays, whichever thread found it.
+        assert_eq!(top_paths(top), [("x".to_owned(), 30)]);
+    }
+}
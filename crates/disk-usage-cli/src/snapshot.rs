@@ -0,0 +1,380 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::escape::{escape_path, unescape_path};
+use crate::output::{self, Output};
+use crate::scan::{Aggregate, GitMetadata, Group};
+use crate::{print_results, Config, Mode};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tells a snapshot apart from any other JSON file.
+const FORMAT: &str = "duh-snapshot";
+/// Bump this when snapshots change in a way older versions of duh can't read. Version 1 had the
+/// paths of the entries as plain text, which lost the bytes that aren't valid UTF-8.
+const VERSION: u64 = 2;
+
+/// Everything a scan found, see --save.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    format: String,
+    version: u64,
+    /// The path as it was given to duh. Every path in the output starts with it.
+    pub root: String,
+    /// `root` made absolute, on the machine that was scanned.
+    pub absolute_root: Option<String>,
+    /// When the scan finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The name of the mode. Only the bytes counted in this mode are in `entries`.
+    mode: String,
+    pub options: Options,
+    /// What the bytes in `.git` directories are used for, whatever the mode.
+    pub git_metadata: GitMetadata,
+    /// Filled in by `load` from `saved_entries`.
+    #[serde(skip)]
+    pub entries: Vec<Entry>,
+    #[serde(rename = "entries")]
+    saved_entries: Vec<SavedEntry>,
+}
+
+/// The options that decided what the sizes in a snapshot mean.
+#[derive(Serialize, Deserialize)]
+pub struct Options {
+    pub apparent: bool,
+    pub hidden: bool,
+    pub depth: Option<u64>,
+    pub direct_size: bool,
+    /// Whether tracked and untracked bytes were told apart.
+    pub git_status: bool,
+    pub count_links: bool,
+    pub one_file_system: bool,
+    pub omit_excluded: bool,
+}
+
+pub struct Entry {
+    /// Relative to the root.
+    pub path: PathBuf,
+    pub group: Group,
+}
+
+/// An entry as it is in the file.
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    /// Escaped with `escape_path`, so that the path reads back byte for byte.
+    path: String,
+    #[serde(flatten)]
+    group: Group,
+}
+
+impl Snapshot {
+    pub fn mode(&self) -> Option<Mode> {
+        Mode::from_str(&self.mode, false).ok()
+    }
+}
+
+/// Write what the scan of `root` found to `file`. Like the output of a scan, the snapshot only
+/// has the bytes counted in the current mode, and only down to --depth.
+pub fn save(file: &Path, aggregate: &Aggregate, root: &str, config: &Config) {
+    let mut groups: Vec<(&PathBuf, &Group)> = aggregate.groups.iter().collect();
+    groups.sort_by(|a, b| a.0.cmp(b.0));
+    let saved_entries = groups
+        .into_iter()
+        .map(|(path, group)| SavedEntry {
+            path: escape_path(path),
+            group: *group,
+        })
+        .collect();
+    let snapshot = Snapshot {
+        format: FORMAT.to_owned(),
+        version: VERSION,
+        root: root.to_owned(),
+        absolute_root: Path::new(root)
+            .canonicalize()
+            .ok()
+            .map(|absolute| absolute.to_string_lossy().into_owned()),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs()),
+        mode: config.mode.name().to_owned(),
+        options: Options {
+            apparent: config.use_apparent_size,
+            hidden: config.include_hidden,
+            depth: config.max_depth,
+            direct_size: !config.use_recursive_size,
+            git_status: config.git_status,
+            count_links: config.count_links,
+            one_file_system: config.one_file_system,
+            omit_excluded: config.omit_excluded,
+        },
+        git_metadata: aggregate.git_metadata,
+        entries: Vec::new(),
+        saved_entries,
+    };
+
+    let written = File::create(file).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &snapshot)?;
+        writer.flush()
+    });
+    if let Err(err) = written {
+        eprintln!("{}: {}", file.display(), err);
+        std::process::exit(1);
+    }
+}
+
+/// Read a snapshot written by `save`.
+pub fn load(file: &Path) -> Snapshot {
+    match std::fs::read(file).map_err(|err| err.to_string()).and_then(|bytes| parse(&bytes)) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            eprintln!("{}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<Snapshot, String> {
+    let mut snapshot = match serde_json::from_slice::<Snapshot>(bytes) {
+        Ok(snapshot) if snapshot.format == FORMAT => snapshot,
+        Ok(_) => return Err("not a snapshot saved with duh --save".to_owned()),
+        Err(err) => return Err(format!("not a snapshot saved with duh --save: {}", err)),
+    };
+    if snapshot.version > VERSION {
+        return Err(format!(
+            "saved by a newer version of duh (snapshot version {}, this one reads up to {})",
+            snapshot.version, VERSION
+        ));
+    }
+    if snapshot.mode().is_none() {
+        return Err(format!("unknown mode `{}`", snapshot.mode));
+    }
+    for SavedEntry { path, group } in std::mem::take(&mut snapshot.saved_entries) {
+        let decoded = if snapshot.version < 2 {
+            Some(PathBuf::from(&path))
+        } else {
+            unescape_path(&path)
+        };
+        let Some(path) = decoded else {
+            return Err(format!("`{}` isn't a path escaped like duh --save does", path));
+        };
+        snapshot.entries.push(Entry { path, group });
+    }
+    Ok(snapshot)
+}
+
+pub fn command(args: impl IntoIterator<Item = Arg>) -> clap::Command {
+    clap::Command::new("open")
+        .about("Show a snapshot saved with --save, without scanning")
+        .long_about("Show a snapshot saved with --save, without scanning. Takes the same options as a scan, like -h, --sort, --tree, --format or --web. A snapshot saved in du mode can be shown in any --mode, leaving out the empty files that mode can't tell apart. Otherwise it can only be shown in the mode it was saved in.")
+        .disable_help_flag(true)
+        .args(args)
+        .arg(
+            Arg::new("FILE")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .help("The snapshot to show"),
+        )
+}
+
+pub fn open(matches: &ArgMatches) {
+    let file = matches.get_one::<PathBuf>("FILE").unwrap();
+    let snapshot = load(file);
+    let mut config = Config::parse(matches.clone());
+    if config.save.is_some() {
+        eprintln!("{}: is a snapshot already, copy it instead of using --save", file.display());
+        std::process::exit(1);
+    }
+    if config.keep_entries {
+        eprintln!("A snapshot only has the sizes of directories and files, not what ncdu needs for --format ncdu");
+        std::process::exit(1);
+    }
+
+    let saved_mode = snapshot.mode().unwrap();
+    if matches.value_source("mode") != Some(ValueSource::CommandLine) {
+        config.mode = saved_mode;
+    } else if saved_mode != Mode::Du && config.mode != saved_mode {
+        eprintln!(
+            "{}: saved with --mode {}, so it only has the bytes of that mode",
+            file.display(),
+            saved_mode.name()
+        );
+        std::process::exit(1);
+    }
+    if matches!(config.mode, Mode::Tracked | Mode::Untracked) && !snapshot.options.git_status {
+        eprintln!("{}: saved without telling tracked and untracked files apart", file.display());
+        std::process::exit(1);
+    }
+    config.use_apparent_size = snapshot.options.apparent;
+    config.use_recursive_size = !snapshot.options.direct_size;
+    config.git_status = snapshot.options.git_status;
+
+    eprintln!(
+        "Snapshot of {} - showing {}",
+        snapshot.absolute_root.as_deref().unwrap_or(&snapshot.root),
+        config.mode.description()
+    );
+    let mut aggregate = aggregate(snapshot.entries, &config);
+    aggregate.git_metadata = snapshot.git_metadata;
+    let mut out = Output::new(config.output.as_deref());
+    if let Err(err) = print_results(aggregate, &snapshot.root, &config, &mut out).and_then(|()| out.flush()) {
+        output::exit_on_error(err);
+    }
+}
+
+/// An aggregate like the scan that saved `entries` would have made with `config`.
+pub fn aggregate(entries: Vec<Entry>, config: &Config) -> Aggregate {
+    let mut aggregate = Aggregate::default();
+    for Entry { path, group } in entries {
+        if config.max_depth.is_some_and(|depth| path.components().count() as u64 > depth) {
+            continue;
+        }
+        // Entries without any bytes in a mode other than du wouldn't have been found in it.
+        if group.size(config.mode) == 0 && config.mode != Mode::Du && !path.as_os_str().is_empty() {
+            continue;
+        }
+        let root = path.as_os_str().is_empty();
+        if root || !config.use_recursive_size {
+            aggregate.mode_total += group.size(config.mode);
+        }
+        aggregate.groups.insert(path, group);
+    }
+    aggregate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, load, parse, save, Entry};
+    use crate::scan::{Aggregate, Group};
+    use crate::tests::TempDir;
+    use crate::{command, Config};
+    use std::path::{Path, PathBuf};
+
+    fn config(args: &[&str]) -> Config {
+        Config::parse(command().get_matches_from([&["duh"], args].concat()))
+    }
+
+    fn group(ignored: u64, not_ignored: u64) -> Group {
+        Group {
+            ignored,
+            not_ignored,
+            ..Group::default()
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let td = TempDir::new();
+        #[cfg(unix)]
+        let odd = {
+            use std::os::unix::ffi::OsStrExt;
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"src/odd\xff\\"))
+        };
+        #[cfg(not(unix))]
+        let odd = PathBuf::from("src/odd\\");
+        let mut scanned = Aggregate::default();
+        scanned.groups.insert(PathBuf::new(), group(1, 6));
+        scanned.groups.insert(odd.clone(), group(1, 2));
+        scanned.groups.insert(PathBuf::from("src"), group(1, 6));
+        scanned.git_metadata.packs = 42;
+        let root = td.path().to_str().unwrap();
+        let file = td.path().join("snapshot.json");
+        save(&file, &scanned, root, &config(&["--apparent", "-d", "3", "-x", "--count-links", root]));
+
+        let snapshot = load(&file);
+        assert_eq!(snapshot.root, root);
+        assert_eq!(snapshot.git_metadata.packs, 42);
+        let options = &snapshot.options;
+        assert!(options.apparent && options.count_links && options.one_file_system);
+        assert!(!options.hidden && !options.direct_size && !options.omit_excluded && !options.git_status);
+        assert_eq!(options.depth, Some(3));
+        let entries: Vec<(&Path, u64)> = snapshot
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry.group.not_ignored))
+            .collect();
+        // In path order, and byte for byte.
+        assert_eq!(entries, [(Path::new(""), 6), (Path::new("src"), 6), (odd.as_path(), 2)]);
+    }
+
+    #[test]
+    fn rejects() {
+        let snapshot = |format: &str, version: u64, mode: &str| {
+            let json = format!(
+                r#"{{"format":"{}","version":{},"root":".","absolute_root":null,"timestamp":0,"mode":"{}",
+                "options":{{"apparent":false,"hidden":false,"depth":null,"direct_size":false,"git_status":false,
+                "count_links":false,"one_file_system":false,"omit_excluded":false}},
+                "git_metadata":{{"packs":0,"loose_objects":0,"lfs":0,"worktrees":0,"other":0}},
+                "entries":[{{"path":"a\\\\b","not_ignored":1,"ignored":0,"git_metadata":0,"tracked":0,
+                "untracked":0,"count":1,"mtime":0,"apparent_size":1,"disk_size":0,"is_file":true}}]}}"#,
+                format, version, mode
+            );
+            parse(json.as_bytes())
+        };
+        let saved = snapshot("duh-snapshot", 2, "du").unwrap();
+        assert_eq!(saved.entries[0].path, Path::new("a\\b"));
+        // Version 1 didn't escape paths.
+        assert_eq!(snapshot("duh-snapshot", 1, "du").unwrap().entries[0].path, Path::new("a\\\\b"));
+        assert!(parse(b"{}").is_err());
+        assert_eq!(snapshot("other", 2, "du").err().unwrap(), "not a snapshot saved with duh --save");
+        assert!(snapshot("duh-snapshot", 3, "du").err().unwrap().contains("newer version"));
+        assert_eq!(snapshot("duh-snapshot", 2, "everything").err().unwrap(), "unknown mode `everything`");
+    }
+
+    #[test]
+    fn aggregate_with_depth_and_mode() {
+        let entries = || {
+            vec![
+                Entry {
+                    path: PathBuf::new(),
+                    group: group(5, 7),
+                },
+                Entry {
+                    path: PathBuf::from("src"),
+                    group: group(0, 7),
+                },
+                Entry {
+                    path: PathBuf::from("target"),
+                    group: group(5, 0),
+                },
+                Entry {
+                    path: PathBuf::from("target/debug"),
+                    group: group(5, 0),
+                },
+            ]
+        };
+        let paths = |aggregate: Aggregate| aggregate.groups.into_keys().collect::<Vec<PathBuf>>();
+
+        let du = aggregate(entries(), &config(&["-d", "1"]));
+        assert_eq!(du.mode_total, 12);
+        assert_eq!(paths(du), ["", "src", "target"].map(PathBuf::from));
+
+        // `src` has no ignored bytes, so a scan in this mode wouldn't have found it.
+        let ignored = aggregate(entries(), &config(&["--mode", "ignored"]));
+        assert_eq!(ignored.mode_total, 5);
+        assert_eq!(paths(ignored), ["", "target", "target/debug"].map(PathBuf::from));
+
+        let not_ignored = aggregate(entries(), &config(&["--mode", "not-ignored", "-d", "0"]));
+        assert_eq!(paths(not_ignored), [PathBuf::new()]);
+
+        // With --direct-size every entry counts towards the total.
+        let direct = aggregate(entries(), &config(&["--direct-size", "--mode", "ignored"]));
+        assert_eq!(direct.mode_total, 15);
+    }
+}
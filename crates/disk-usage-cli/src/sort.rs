@@ -0,0 +1,183 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::scan::Group;
+use crate::Mode;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What --sort orders entries by. Sizes, counts and times sort biggest or newest first, names
+/// alphabetically.
+#[derive(Copy, PartialEq, Eq, Clone, clap::ValueEnum)]
+pub enum SortKey {
+    Size,
+    Name,
+    Count,
+    Mtime,
+}
+
+#[derive(Copy, Clone)]
+pub struct Sort {
+    pub key: SortKey,
+    pub reverse: bool,
+    /// Sort every entry against every other, instead of only against its siblings.
+    pub flat: bool,
+}
+
+impl Sort {
+    fn compare(&self, a: &(PathBuf, Group), b: &(PathBuf, Group), mode: Mode) -> Ordering {
+        let order = match self.key {
+            SortKey::Size => b.1.size(mode).cmp(&a.1.size(mode)),
+            SortKey::Name => a.0.file_name().cmp(&b.0.file_name()),
+            SortKey::Count => b.1.count.cmp(&a.1.count),
+            SortKey::Mtime => b.1.mtime.cmp(&a.1.mtime),
+        };
+        let order = if self.reverse { order.reverse() } else { order };
+        // Ties are broken by path, so the output doesn't depend on the order of the walk.
+        order.then_with(|| a.0.cmp(&b.0))
+    }
+
+    /// Order `entries` for printing.
+    ///
+    /// Unless the sort is flat, only siblings are compared with each other, and every directory
+    /// stays next to its contents: right before them with `parent_first`, or right after them
+    /// like `du` prints them.
+    pub fn apply(&self, entries: Vec<(PathBuf, Group)>, mode: Mode, parent_first: bool) -> Vec<(PathBuf, Group)> {
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        if self.flat {
+            order.sort_by(|&a, &b| self.compare(&entries[a], &entries[b], mode));
+        } else {
+            order = self.hierarchical_order(&entries, mode, parent_first);
+        }
+
+        let mut entries: Vec<Option<(PathBuf, Group)>> = entries.into_iter().map(Some).collect();
+        order.into_iter().filter_map(|i| entries[i].take()).collect()
+    }
+
+    fn hierarchical_order(&self, entries: &[(PathBuf, Group)], mode: Mode, parent_first: bool) -> Vec<usize> {
+        let index: HashMap<&Path, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| (path.as_path(), i))
+            .collect();
+        // Entries whose parent isn't listed, usually only the root, are sorted as siblings.
+        let mut roots = vec![];
+        let mut children: Vec<Vec<usize>> = vec![vec![]; entries.len()];
+        for (i, (path, _)) in entries.iter().enumerate() {
+            match path.parent().and_then(|parent| index.get(parent)) {
+                Some(&parent) => children[parent].push(i),
+                None => roots.push(i),
+            }
+        }
+        let compare = |a: &usize, b: &usize| self.compare(&entries[*a], &entries[*b], mode);
+        roots.sort_by(compare);
+        for siblings in &mut children {
+            siblings.sort_by(compare);
+        }
+
+        // Walk the tree without recursing, since it can be as deep as the file system.
+        let mut order = Vec::with_capacity(entries.len());
+        let mut stack: Vec<(usize, bool)> = roots.into_iter().rev().map(|i| (i, false)).collect();
+        while let Some((i, expanded)) = stack.pop() {
+            if expanded {
+                order.push(i);
+                continue;
+            }
+            if parent_first {
+                order.push(i);
+            } else {
+                stack.push((i, true));
+            }
+            stack.extend(children[i].iter().rev().map(|&child| (child, false)));
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sort, SortKey};
+    use crate::scan::Group;
+    use crate::Mode;
+    use std::path::PathBuf;
+
+    fn entries() -> Vec<(PathBuf, Group)> {
+        [
+            ("", 100, 6),
+            ("a", 30, 3),
+            ("a/x", 10, 1),
+            ("a/y", 20, 2),
+            ("b", 60, 2),
+            ("b/z", 60, 2),
+            ("c", 10, 1),
+        ]
+        .into_iter()
+        .map(|(path, size, count)| {
+            let group = Group {
+                not_ignored: size,
+                count,
+                mtime: 1000 - size,
+                ..Group::default()
+            };
+            (PathBuf::from(path), group)
+        })
+        .collect()
+    }
+
+    fn sorted(key: SortKey, reverse: bool, flat: bool, parent_first: bool) -> Vec<String> {
+        Sort { key, reverse, flat }
+            .apply(entries(), Mode::Du, parent_first)
+            .into_iter()
+            .map(|(path, _)| path.to_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn hierarchical() {
+        // Like du, every directory comes right after its contents.
+        assert_eq!(sorted(SortKey::Size, false, false, false), ["b/z", "b", "a/y", "a/x", "a", "c", ""]);
+        assert_eq!(sorted(SortKey::Size, false, false, true), ["", "b", "b/z", "a", "a/y", "a/x", "c"]);
+        assert_eq!(sorted(SortKey::Size, true, false, true), ["", "c", "a", "a/x", "a/y", "b", "b/z"]);
+        assert_eq!(sorted(SortKey::Name, false, false, true), ["", "a", "a/x", "a/y", "b", "b/z", "c"]);
+        assert_eq!(sorted(SortKey::Mtime, false, false, true), ["", "c", "a", "a/x", "a/y", "b", "b/z"]);
+    }
+
+    #[test]
+    fn flat() {
+        // Ties are broken by path.
+        assert_eq!(sorted(SortKey::Size, false, true, false), ["", "b", "b/z", "a", "a/y", "a/x", "c"]);
+        assert_eq!(sorted(SortKey::Count, false, true, false), ["", "a", "a/y", "b", "b/z", "a/x", "c"]);
+        assert_eq!(sorted(SortKey::Name, false, true, true), ["", "a", "b", "c", "a/x", "a/y", "b/z"]);
+        assert_eq!(sorted(SortKey::Size, true, true, false), ["a/x", "c", "a/y", "a", "b", "b/z", ""]);
+    }
+
+    #[test]
+    fn missing_parents() {
+        // With a threshold or --depth some parents aren't listed, their children are sorted
+        // with the root.
+        let mut entries = entries();
+        entries.retain(|(path, _)| path.as_os_str() != "a");
+        let sorted: Vec<PathBuf> = Sort {
+            key: SortKey::Size,
+            reverse: false,
+            flat: false,
+        }
+        .apply(entries, Mode::Du, true)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+        assert_eq!(sorted, ["", "b", "b/z", "c", "a/y", "a/x"].map(PathBuf::from));
+    }
+}
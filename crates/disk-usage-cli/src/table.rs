@@ -0,0 +1,103 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::format_human_readable;
+use std::cmp::Ordering;
+
+/// A size as a table cell, with -h like a scan prints it, otherwise in 512-byte blocks. A size
+/// that isn't known is `-`.
+pub fn format_size(size: Option<u64>, human_readable: bool) -> String {
+    match size {
+        Some(size) if human_readable => format_human_readable(size).trim_start().to_owned(),
+        Some(size) => (size / 512).to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+/// How much a size changed from `old` to `new` as a table cell, starting with + or -.
+pub fn format_delta(old: u64, new: u64, human_readable: bool) -> String {
+    let delta = if human_readable {
+        new as i64 - old as i64
+    } else {
+        (new / 512) as i64 - (old / 512) as i64
+    };
+    match delta.cmp(&0) {
+        Ordering::Equal => "0".to_owned(),
+        _ if !human_readable => format!("{:+}", delta),
+        Ordering::Greater => format!("+{}", format_human_readable(delta.unsigned_abs()).trim_start()),
+        Ordering::Less => format!("-{}", format_human_readable(delta.unsigned_abs()).trim_start()),
+    }
+}
+
+/// Join the cells of each row, with every column right-aligned to its widest cell.
+pub fn align_columns<const N: usize>(rows: &[[String; N]]) -> Vec<String> {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                .collect();
+            cells.join(" ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{align_columns, format_delta, format_size};
+
+    #[test]
+    fn delta_in_blocks() {
+        assert_eq!(format_delta(0, 0, false), "0");
+        assert_eq!(format_delta(1024, 2048, false), "+2");
+        assert_eq!(format_delta(2048, 1024, false), "-2");
+        // Blocks are rounded down before they are compared, like the sizes next to them.
+        assert_eq!(format_delta(600, 1000, false), "0");
+        assert_eq!(format_delta(1023, 1024, false), "+1");
+        assert_eq!(format_delta(1024, 1535, false), "0");
+        assert_eq!(format_delta(511, 0, false), "0");
+    }
+
+    #[test]
+    fn delta_human_readable() {
+        assert_eq!(format_delta(600, 1000, true), "+400B");
+        assert_eq!(format_delta(0, 1536, true), "+1.5K");
+        assert_eq!(format_delta(3 << 20, 1 << 20, true), "-2.0M");
+        assert_eq!(format_delta(1000, 1000, true), "0");
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(format_size(Some(1535), false), "2");
+        assert_eq!(format_size(Some(1536), true), "1.5K");
+        assert_eq!(format_size(None, true), "-");
+    }
+
+    #[test]
+    fn columns() {
+        let rows = [
+            ["change", "old"].map(String::from),
+            ["+1", "12345"].map(String::from),
+            ["-1.5K", "-"].map(String::from),
+        ];
+        assert_eq!(align_columns(&rows), ["change   old", "    +1 12345", " -1.5K     -"]);
+    }
+}
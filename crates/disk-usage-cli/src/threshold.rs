@@ -0,0 +1,238 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::scan::Group;
+use crate::Mode;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Parse a size like `512`, `10K`, `1.5M` or `2GiB`. Suffixes are powers of 1024, like `du`.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let digits = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, suffix) = text.split_at(digits);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{}` is not a size, expected something like 10M", text))?;
+    let suffix = suffix.trim().to_ascii_uppercase();
+    let suffix = suffix
+        .strip_suffix("IB")
+        .or_else(|| suffix.strip_suffix('B'))
+        .unwrap_or(&suffix);
+    let exponent = match suffix {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return Err(format!("unknown size suffix in `{}`, expected K, M, G, T or P", text)),
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+/// Replace the entries smaller than `threshold` with one `<N smaller entries>` entry per
+/// directory, holding their combined size. That keeps the sizes of the directories that are
+/// left consistent with what is listed below them.
+///
+/// When sizes are `recursive` an entry's size already includes everything below it, so the
+/// contents of a small directory disappear along with it. Otherwise each small entry is folded
+/// into its closest ancestor that stays, and a small directory stays when anything below it
+/// does, so that the parent of every entry that is left is listed too.
+pub fn fold_small_entries(
+    entries: Vec<(PathBuf, Group)>,
+    threshold: u64,
+    mode: Mode,
+    recursive: bool,
+) -> Vec<(PathBuf, Group)> {
+    let is_small: HashMap<&Path, bool> = entries
+        .iter()
+        .map(|(path, group)| (path.as_path(), group.size(mode) < threshold))
+        .collect();
+    // Entries without a listed parent, like the root, always stay.
+    let mut stays: HashSet<&Path> = entries
+        .iter()
+        .map(|(path, _)| path.as_path())
+        .filter(|path| match path.parent() {
+            Some(parent) if is_small.contains_key(parent) => !is_small[path],
+            _ => true,
+        })
+        .collect();
+    if !recursive {
+        let ancestors: Vec<&Path> = stays
+            .iter()
+            .flat_map(|path| path.ancestors().skip(1))
+            .filter(|ancestor| is_small.contains_key(ancestor))
+            .collect();
+        stays.extend(ancestors);
+    }
+    let stays = |path: &Path| stays.contains(path) || !is_small.contains_key(path);
+
+    let keep: Vec<bool> = entries.iter().map(|(path, _)| stays(path)).collect();
+
+    let mut folded: HashMap<PathBuf, (u64, Group)> = HashMap::new();
+    for ((path, group), _) in entries.iter().zip(&keep).filter(|(_, keep)| !**keep) {
+        let mut ancestor = path.parent().unwrap();
+        if recursive {
+            if !stays(ancestor) {
+                continue;
+            }
+        } else {
+            while !stays(ancestor) {
+                ancestor = ancestor.parent().unwrap();
+            }
+        }
+        let (count, sizes) = folded
+            .entry(ancestor.to_path_buf())
+            .or_insert((0, Group::default()));
+        *count += 1;
+        sizes.merge(group);
+    }
+
+    let mut kept: Vec<(PathBuf, Group)> = entries
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(entry, keep)| keep.then_some(entry))
+        .collect();
+    for (directory, (count, group)) in folded {
+        let name = if count == 1 {
+            "<1 smaller entry>".to_owned()
+        } else {
+            format!("<{} smaller entries>", count)
+        };
+        kept.push((directory.join(name), group));
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fold_small_entries, parse_size};
+    use crate::scan::Group;
+    use crate::Mode;
+    use std::path::PathBuf;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 << 10));
+        assert_eq!(parse_size("1.5M"), Ok(3 << 19));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size(" 3 gb "), Ok(3 << 30));
+        assert_eq!(parse_size("1t"), Ok(1 << 40));
+    }
+
+    #[test]
+    fn bad_sizes() {
+        assert!(parse_size("10X").unwrap_err().contains("unknown size suffix"));
+        assert!(parse_size("10KX").is_err());
+        assert!(parse_size("M").unwrap_err().contains("not a size"));
+        assert!(parse_size("1.2.3K").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    fn entries(sizes: &[(&str, u64)]) -> Vec<(PathBuf, Group)> {
+        sizes
+            .iter()
+            .map(|&(path, size)| {
+                let group = Group {
+                    not_ignored: size,
+                    count: 1,
+                    ..Group::default()
+                };
+                (PathBuf::from(path), group)
+            })
+            .collect()
+    }
+
+    fn summary(entries: Vec<(PathBuf, Group)>) -> Vec<(String, u64, u64)> {
+        let mut summary: Vec<(String, u64, u64)> = entries
+            .into_iter()
+            .map(|(path, group)| (path.to_str().unwrap().to_owned(), group.not_ignored, group.count))
+            .collect();
+        summary.sort();
+        summary
+    }
+
+    #[test]
+    fn fold_recursive() {
+        let entries = entries(&[
+            ("", 1000),
+            ("big", 900),
+            ("big/a", 850),
+            ("big/b", 30),
+            ("big/c", 20),
+            ("small", 40),
+            ("small/x", 25),
+            ("small/y", 15),
+        ]);
+        let folded = fold_small_entries(entries, 50, Mode::Du, true);
+        // The contents of `small` are in its size already, so they go away with it.
+        assert_eq!(
+            summary(folded),
+            [
+                ("".to_owned(), 1000, 1),
+                ("<1 smaller entry>".to_owned(), 40, 1),
+                ("big".to_owned(), 900, 1),
+                ("big/<2 smaller entries>".to_owned(), 50, 2),
+                ("big/a".to_owned(), 850, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_direct() {
+        let entries = entries(&[
+            ("", 10),
+            ("dir", 4),
+            ("dir/big", 100),
+            ("dir/small", 6),
+            ("dir/sub", 4),
+            ("dir/sub/tiny", 1),
+        ]);
+        let folded = fold_small_entries(entries, 50, Mode::Du, false);
+        // With --direct-size `dir` is small, but stays because `dir/big` does. Each small entry
+        // is folded into the closest ancestor that stays, and the total stays the same.
+        assert_eq!(
+            summary(folded),
+            [
+                ("".to_owned(), 10, 1),
+                ("dir".to_owned(), 4, 1),
+                ("dir/<3 smaller entries>".to_owned(), 11, 3),
+                ("dir/big".to_owned(), 100, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_direct_keeps_ancestors() {
+        let entries = entries(&[
+            ("", 1),
+            ("a", 1),
+            ("a/b", 1),
+            ("a/b/c", 1),
+            ("a/b/c/big", 100),
+            ("a/b/other", 1),
+            ("z", 1),
+        ]);
+        let folded = fold_small_entries(entries, 50, Mode::Du, false);
+        let paths: Vec<String> = summary(folded).into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(
+            paths,
+            ["", "<1 smaller entry>", "a", "a/b", "a/b/<1 smaller entry>", "a/b/c", "a/b/c/big"]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::Output;
+use crate::scan::Group;
+use crate::sort::{Sort, SortKey};
+use crate::{format_human_readable, Config};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The bar shows what the bytes of an entry are, like the colors of the sunburst chart.
+const NOT_IGNORED_CELL: &str = "█";
+const IGNORED_CELL: &str = "░";
+const GIT_METADATA_CELL: &str = "▒";
+
+/// Print `entries` as a tree, one line per entry, each with its share of its parent.
+pub fn print(entries: Vec<(PathBuf, Group)>, root: &str, config: &Config, out: &mut Output) -> io::Result<()> {
+    // Only siblings can be reordered, the tree decides everything else.
+    let sort = match config.sort {
+        Some(sort) => Sort { flat: false, ..sort },
+        None => Sort {
+            key: SortKey::Name,
+            reverse: false,
+            flat: false,
+        },
+    };
+    let entries = sort.apply(entries, config.mode, true);
+
+    let index: HashMap<&Path, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (path, _))| (path.as_path(), i))
+        .collect();
+    let parents: Vec<Option<usize>> = entries
+        .iter()
+        .map(|(path, _)| path.parent().and_then(|parent| index.get(parent)).copied())
+        .collect();
+    // Entries come parent first, so the last child of a parent is the last one to name it.
+    let mut last_child: HashMap<Option<usize>, usize> = HashMap::new();
+    for (i, parent) in parents.iter().enumerate() {
+        last_child.insert(*parent, i);
+    }
+
+    let max_size = entries.iter().map(|(_, group)| group.size(config.mode)).max().unwrap_or(0);
+    let size_width = if config.human_readable {
+        // Human readable sizes are always 5 characters wide.
+        5
+    } else {
+        (max_size / 512).to_string().len()
+    };
+    let width = terminal_width(out);
+    let bar_width = (width / 4).clamp(8, 40);
+    // size, percent and bar, each followed by a space
+    let name_width = width.saturating_sub(size_width + 1 + 7 + bar_width + 1).max(10);
+
+    // Whether each ancestor, by depth, has siblings still to come.
+    let mut open: Vec<bool> = vec![];
+    let mut depths: Vec<usize> = Vec::with_capacity(entries.len());
+    for (i, (path, group)) in entries.iter().enumerate() {
+        let size = group.size(config.mode);
+        let (depth, parent_size) = match parents[i] {
+            Some(parent) => (depths[parent] + 1, entries[parent].1.size(config.mode)),
+            None => (0, size),
+        };
+        depths.push(depth);
+
+        let mut tree = String::new();
+        let name = if depth == 0 {
+            Path::new(root).join(path).display().to_string()
+        } else {
+            open.truncate(depth - 1);
+            for &more in &open {
+                tree.push_str(if more { "│  " } else { "   " });
+            }
+            let is_last = last_child.get(&parents[i]) == Some(&i);
+            tree.push_str(if is_last { "└─ " } else { "├─ " });
+            open.push(!is_last);
+            path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+        };
+        if depth == 0 {
+            open.clear();
+        }
+
+        let share = if parent_size == 0 {
+            0.0
+        } else {
+            size as f64 / parent_size as f64
+        };
+        let size = if config.human_readable {
+            format_human_readable(size)
+        } else {
+            format!("{:>width$}", size / 512, width = size_width)
+        };
+        let label = console::truncate_str(&format!("{}{}", tree, name), name_width, "…").into_owned();
+        writeln!(out, "{} {:>5.1}% {} {}", size, 100.0 * share, bar(group, share, bar_width), label)?;
+    }
+    Ok(())
+}
+
+/// A bar `width` cells wide, filled by `share`, split by what kind of bytes `group` holds.
+fn bar(group: &Group, share: f64, width: usize) -> String {
+    // Without --direct-size an entry is never bigger than its parent, with it anything goes.
+    let filled = ((share * width as f64).round() as usize).min(width);
+    let total = group.total();
+    let cells_for = |bytes: u64| {
+        if total == 0 {
+            0
+        } else {
+            (bytes as f64 / total as f64 * filled as f64).round() as usize
+        }
+    };
+    let not_ignored = cells_for(group.not_ignored).min(filled);
+    let git_metadata = cells_for(group.git_metadata).min(filled - not_ignored);
+    let ignored = filled - not_ignored - git_metadata;
+
+    NOT_IGNORED_CELL.repeat(not_ignored)
+        + &IGNORED_CELL.repeat(ignored)
+        + &GIT_METADATA_CELL.repeat(git_metadata)
+        + &" ".repeat(width - filled)
+}
+
+/// The width of the terminal the results are printed to, or 80 columns.
+fn terminal_width(out: &Output) -> usize {
+    out.terminal_width()
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+        .unwrap_or(80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar, print};
+    use crate::output::Output;
+    use crate::scan::Group;
+    use crate::tests::TempDir;
+    use crate::{command, Config};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn group(not_ignored: u64, ignored: u64, git_metadata: u64) -> Group {
+        Group {
+            not_ignored,
+            ignored,
+            git_metadata,
+            ..Group::default()
+        }
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(bar(&group(50, 50, 0), 1.0, 8), "████░░░░");
+        assert_eq!(bar(&group(2, 1, 1), 0.5, 8), "██░▒    ");
+        assert_eq!(bar(&group(0, 0, 0), 0.0, 4), "    ");
+        // With --direct-size a child can be bigger than its parent.
+        assert_eq!(bar(&group(10, 0, 0), 2.5, 4), "████");
+    }
+
+    #[test]
+    fn lines() {
+        let entries = [
+            ("", group(1024, 0, 0)),
+            ("b", group(256, 0, 0)),
+            ("a", group(768, 0, 0)),
+            ("a/y", group(256, 0, 0)),
+            ("a/x", group(512, 0, 0)),
+        ];
+        let entries: Vec<(PathBuf, Group)> = entries
+            .into_iter()
+            .map(|(path, group)| (PathBuf::from(path), group))
+            .collect();
+        let config = Config::parse(command().get_matches_from(["duh", "--tree"]));
+        let td = TempDir::new();
+        let file = td.path().join("tree");
+        let mut out = Output::new(Some(&file));
+        print(entries, "root", &config, &mut out).unwrap();
+        out.flush().unwrap();
+        let text = std::fs::read_to_string(file).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        // Siblings by name, every directory before its contents.
+        for (line, (share, label)) in lines.iter().zip([
+            ("100.0%", " root/"),
+            (" 75.0%", " ├─ a"),
+            (" 66.7%", " │  ├─ x"),
+            (" 33.3%", " │  └─ y"),
+            (" 25.0%", " └─ b"),
+        ]) {
+            assert!(line.contains(share) && line.ends_with(label), "{}", line);
+        }
+    }
+}
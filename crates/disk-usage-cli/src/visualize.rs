@@ -15,6 +15,8 @@
 use serde::Serialize;
 use std::collections::HashMap;
 use std::io::ErrorKind;
+use crate::scan::{file_type, Group};
+use ignore::types::Types;
 use std::path::PathBuf;
 
 const HTML_CONTENT: &str = include_str!(concat!(env!("OUT_DIR"), "/index.html"));
@@ -33,8 +35,23 @@ struct InputFile {
     imports: Vec<ImportRecord>,
     #[serde(skip_serializing_if = "Option::is_none")]
     format: Option<&'static str>,
+    /// Bit set of the categories found below this path, see `CATEGORY_IGNORED` and friends.
+    /// `format` only tells ignored and not ignored apart.
+    categories: u8,
+    /// The name of the file type, when the file matches one of the `--by-type` definitions.
+    #[serde(rename = "fileType", skip_serializing_if = "Option::is_none")]
+    file_type: Option<String>,
+    /// Bytes gained since the older snapshot of `duh diff`, negative when they shrank.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<i64>,
 }
 
+const CATEGORY_IGNORED: u8 = 1;
+/// Not ignored, and either tracked or not inside a git repository.
+const CATEGORY_NOT_IGNORED: u8 = 2;
+const CATEGORY_UNTRACKED: u8 = 4;
+const CATEGORY_GIT_METADATA: u8 = 8;
+
 #[derive(Serialize)]
 struct OutputFile {
     bytes: u64,
@@ -55,14 +72,19 @@ struct InputForOutput {
     bytesInOutput: u64,
 }
 
-pub fn encode(entries: &Vec<(PathBuf, (u64, u64, bool))>) -> String {
+pub fn encode(
+    entries: &Vec<(PathBuf, Group)>,
+    types: Option<&Types>,
+    deltas: Option<&HashMap<PathBuf, i64>>,
+) -> String {
     let mut total_size = 0;
     let mut inputs = HashMap::new();
     let mut output_inputs = HashMap::new();
 
     // Since groups is already sorted by prefix, we can look ahead to check for children
     for i in 0..entries.len() {
-        let (ref current_path, (not_ignored_size, ignored_size, _is_file)) = entries[i];
+        let (ref current_path, group) = entries[i];
+        let (not_ignored_size, ignored_size) = (group.not_ignored, group.ignored);
         let current_str = current_path.to_string_lossy();
 
         // Skip empty paths
@@ -76,7 +98,7 @@ pub fn encode(entries: &Vec<(PathBuf, (u64, u64, bool))>) -> String {
             .map(|(next_path, _)| next_path.starts_with(current_path))
             .unwrap_or(false);
 
-        let size = not_ignored_size + ignored_size;
+        let size = group.total();
         // Only process paths that aren't prefixes of later paths (leaf nodes)
         if !is_prefix {
             inputs.insert(
@@ -93,6 +115,12 @@ pub fn encode(entries: &Vec<(PathBuf, (u64, u64, bool))>) -> String {
                     } else {
                         None
                     },
+                    categories: categories(&group),
+                    file_type: types
+                        .filter(|_| group.is_file && group.git_metadata == 0)
+                        .and_then(|types| file_type(types, current_path))
+                        .map(str::to_owned),
+                    delta: deltas.map(|deltas| deltas.get(current_path).copied().unwrap_or(0)),
                 },
             );
 
@@ -124,10 +152,32 @@ pub fn encode(entries: &Vec<(PathBuf, (u64, u64, bool))>) -> String {
     json
 }
 
+fn categories(group: &Group) -> u8 {
+    let mut categories = 0;
+    if group.ignored > 0 {
+        categories |= CATEGORY_IGNORED;
+    }
+    if group.not_ignored > group.untracked {
+        categories |= CATEGORY_NOT_IGNORED;
+    }
+    if group.untracked > 0 {
+        categories |= CATEGORY_UNTRACKED;
+    }
+    if group.git_metadata > 0 {
+        categories |= CATEGORY_GIT_METADATA;
+    }
+    categories
+}
+
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
 
-pub fn view_in_browser(entries: &Vec<(PathBuf, (u64, u64, bool))>, open_in_browser: bool) {
+pub fn view_in_browser(
+    entries: &Vec<(PathBuf, Group)>,
+    types: Option<&Types>,
+    deltas: Option<&HashMap<PathBuf, i64>>,
+    open_in_browser: bool,
+) {
     for port in 8001..65535 {
         let address = format!("127.0.0.1:{}", port);
         match TcpListener::bind(&address) {
@@ -138,7 +188,7 @@ pub fn view_in_browser(entries: &Vec<(PathBuf, (u64, u64, bool))>, open_in_brows
             Err(e) => panic!("Failed to bind: {}", e),
             Ok(listener) => {
                 eprintln!("Server running on http://{}", &address);
-                let json = encode(entries);
+                let json = encode(entries, types, deltas);
 
                 if open_in_browser {
                     open::that_detached(format!("http://{}", address)).unwrap();
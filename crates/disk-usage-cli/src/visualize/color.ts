@@ -1,31 +1,49 @@
 import * as styles from "./color.css";
-import { Metafile } from "./metafile";
+import { InputFile, Metafile } from "./metafile";
 import {
   accumulatePath,
   orderChildrenBySize,
   TreeNodeInProgress,
 } from "./tree";
 import {
+  bytesToText,
   hueAngleToColor,
   isSourceMapPath,
   stripDisabledPathPrefix,
+  textToHTML,
 } from "./helpers";
 
 export enum COLOR {
   NONE = 0,
   DIRECTORY = 1,
   FORMAT = 2,
+  TYPE = 3,
+  DELTA = 4,
 }
 
-enum FORMATS {
+// These color by something that can be put into words, so labels show that instead of sizes
+export let hasCategoryLabels = (color: COLOR): boolean =>
+  color === COLOR.FORMAT || color === COLOR.TYPE || color === COLOR.DELTA;
+
+// Only the biggest file types get a color of their own, the rest share otherColor
+const TYPE_COLOR_COUNT = 12;
+
+// Returned for a directory containing several file types
+const MIXED_TYPE = "\0mixed";
+
+// A bit set, matching the "categories" field of the metafile
+export enum FORMATS {
   CJS = 1,
   ESM = 2,
   BOTH = 3,
+  UNTRACKED = 4,
+  GIT = 8,
 }
 
 let previousPatternContext: CanvasRenderingContext2D | undefined;
 let previousPatternRatio: number | undefined;
 let previousPatternScale: number | undefined;
+let previousPatternColor: Color | undefined;
 let patternCanvas = document.createElement("canvas");
 let patternContext = patternCanvas.getContext("2d")!;
 let patternScale = 1;
@@ -38,6 +56,9 @@ let root: TreeNodeInProgress;
 export type Color = string | readonly [string, string];
 export type ColorMapping = Record<string, Color>;
 let colorMapping: ColorMapping = {};
+let typeColors: Record<string, string> = {};
+let typeLabels: Record<string, string> = {};
+let deltas: Record<string, number> = {};
 
 let afterColorMappingUpdate: (() => void) | null = null;
 export let setAfterColorMappingUpdate = (callback: () => void) =>
@@ -56,7 +77,8 @@ export let canvasFillStyleForInputPath = (
     if (
       previousPatternContext !== c ||
       previousPatternRatio !== ratio ||
-      previousPatternScale !== scale
+      previousPatternScale !== scale ||
+      previousPatternColor !== color
     ) {
       let s = Math.round(64 * ratio) / 64;
       let t1: number;
@@ -74,6 +96,7 @@ export let canvasFillStyleForInputPath = (
       previousPatternContext = c;
       previousPatternRatio = ratio;
       previousPatternScale = scale;
+      previousPatternColor = color;
 
       patternCanvas.width = patternCanvas.height = Math.round(64 * s);
       patternContext.scale(s, s);
@@ -170,6 +193,7 @@ export let updateColorMapping = (metafile: Metafile, color: COLOR): void => {
   if (previousColor !== color) {
     previousColor = color;
     colorMapping = {};
+    typeLabels = {};
     colorLegendEl.innerHTML = "";
 
     if (color === COLOR.DIRECTORY) {
@@ -177,6 +201,14 @@ export let updateColorMapping = (metafile: Metafile, color: COLOR): void => {
     } else if (color === COLOR.FORMAT) {
       assignColorsByFormat(colorMapping, root);
       colorLegendEl.innerHTML = formatLegendHTML;
+    } else if (color === COLOR.TYPE) {
+      colorLegendEl.innerHTML = assignTypeColors(metafile);
+      assignColorsByType(colorMapping, root);
+    } else if (color === COLOR.DELTA) {
+      deltas = {};
+      accumulateDeltas(deltas, root);
+      assignColorsByDelta(colorMapping, deltas);
+      colorLegendEl.innerHTML = deltaLegendHTML;
     }
 
     if (afterColorMappingUpdate) afterColorMappingUpdate();
@@ -206,27 +238,70 @@ let assignColorsByDirectory = (
   }
 };
 
+export let grewColor = "hsl(0, 80%, 55%)";
+export let shrankColor = "hsl(145, 80%, 55%)";
+
 export let cjsColor = "hsl(48, 89%, 50%)"; // "#F1C40F"; // hueAngleToColor(3.5)
 export let esmColor = "hsl(145.44, 63.2%, 49.02%)"; // "#2ECC71"; // hueAngleToColor(1)
+export let untrackedColor = "hsl(12, 85%, 56%)";
+export let gitColor = "hsl(210, 70%, 58%)";
 export let otherColor = "#CCC";
-let bothColor = [cjsColor, esmColor] as const;
+
+// A mix of categories is striped with the two that come first here. Untracked files are the
+// ones most likely to be forgotten, so they always stay visible.
+let colorsByPriority: [FORMATS, string][] = [
+  [FORMATS.UNTRACKED, untrackedColor],
+  [FORMATS.CJS, cjsColor],
+  [FORMATS.GIT, gitColor],
+  [FORMATS.ESM, esmColor],
+];
+
+// Stripes are cached by identity, so hand out the same array for the same mix
+let mixedColors: Record<string, readonly [string, string]> = {};
+
+// The categories of an input, or what its format tells when there are none
+export let formatsForInput = (input: InputFile): FORMATS | 0 => {
+  if (input.categories !== undefined) return input.categories;
+  switch (input.format) {
+    case "cjs":
+      return FORMATS.CJS;
+    case "esm":
+      return FORMATS.ESM;
+    case "both":
+      return FORMATS.BOTH;
+    default:
+      return 0;
+  }
+};
 
 let colorForFormats = (formats: FORMATS | 0): Color => {
   if (formats === 0) return otherColor;
-  if (formats === FORMATS.CJS) return cjsColor;
-  if (formats === FORMATS.ESM) return esmColor;
-  if (formats === FORMATS.BOTH) return bothColor;
-  return bothColor;
+  let colors = colorsByPriority
+    .filter(([format]) => formats & format)
+    .map(([, color]) => color);
+  if (colors.length === 1) return colors[0];
+  let key = colors[0] + "|" + colors[1];
+  return mixedColors[key] || (mixedColors[key] = [colors[0], colors[1]]);
 };
 
 export let moduleTypeLabelInputPath = (
   inputPath: string,
   prefix: string
 ): string => {
+  if (previousColor === COLOR.TYPE) {
+    let label = typeLabels[inputPath];
+    return label ? prefix + label : "";
+  }
+  if (previousColor === COLOR.DELTA) {
+    let delta = deltas[inputPath] || 0;
+    return prefix + (delta > 0 ? "+" : delta < 0 ? "-" : "±") + bytesToText(Math.abs(delta));
+  }
   let color = colorMapping[inputPath] || otherColor;
   if (color === otherColor) return "";
   if (color === esmColor) return prefix + "Non-ignored";
   if (color === cjsColor) return prefix + "Ignored";
+  if (color === untrackedColor) return prefix + "Untracked";
+  if (color === gitColor) return prefix + "Git metadata";
   return prefix + "Mixed";
 };
 
@@ -245,27 +320,114 @@ let assignColorsByFormat = (
 
   if (!hasChild) {
     let input = previousMetafile!.inputs[node.inputPath_];
-    let format = input && input.format;
-    switch (format) {
-      case "cjs":
-        formats = FORMATS.CJS;
-        break;
-      case "esm":
-        formats = FORMATS.ESM;
-        break;
-      case "both":
-        formats = FORMATS.BOTH;
-        break;
-      default:
-        formats = 0;
-        break;
-    }
+    formats = input ? formatsForInput(input) : 0;
   }
 
   colorMapping[node.inputPath_] = colorForFormats(formats);
   return formats;
 };
 
+// Hand out colors to the biggest file types, and return the legend for them
+let assignTypeColors = (metafile: Metafile): string => {
+  let bytesByType: Record<string, number> = {};
+  for (let i in metafile.inputs) {
+    let input = metafile.inputs[i];
+    if (input.fileType)
+      bytesByType[input.fileType] = (bytesByType[input.fileType] || 0) + input.bytes;
+  }
+  let types = Object.keys(bytesByType)
+    .sort((a, b) => bytesByType[b] - bytesByType[a] || (a < b ? -1 : 1))
+    .slice(0, TYPE_COLOR_COUNT);
+
+  let legend = "";
+  typeColors = {};
+  types.forEach((type, i) => {
+    let color = hueAngleToColor((i / types.length) * Math.PI * 2);
+    typeColors[type] = color;
+    legend +=
+      `<span class="${styles.chit}" style="background:` +
+      color +
+      '"></span>' +
+      textToHTML(type) +
+      "<small>" +
+      textToHTML(bytesToText(bytesByType[type])) +
+      "</small>";
+  });
+  return (
+    legend +
+    `<span class="${styles.chit}" style="background:` +
+    otherColor +
+    '"></span>Other types'
+  );
+};
+
+let assignColorsByType = (
+  colorMapping: ColorMapping,
+  node: TreeNodeInProgress
+): string => {
+  let children = node.children_;
+  let type = "";
+  let hasChild = false;
+
+  for (let file in children) {
+    let childType = assignColorsByType(colorMapping, children[file]);
+    type = !hasChild || type === childType ? childType : MIXED_TYPE;
+    hasChild = true;
+  }
+
+  if (!hasChild) {
+    let input = previousMetafile!.inputs[node.inputPath_];
+    type = (input && input.fileType) || "";
+  }
+
+  colorMapping[node.inputPath_] = typeColors[type] || otherColor;
+  if (type) typeLabels[node.inputPath_] = type === MIXED_TYPE ? "Mixed" : type;
+  return type;
+};
+
+// Sum up the deltas of the files below each directory
+let accumulateDeltas = (
+  deltas: Record<string, number>,
+  node: TreeNodeInProgress
+): number => {
+  let children = node.children_;
+  let delta = 0;
+  let hasChild = false;
+
+  for (let file in children) {
+    delta += accumulateDeltas(deltas, children[file]);
+    hasChild = true;
+  }
+
+  if (!hasChild) {
+    let input = previousMetafile!.inputs[node.inputPath_];
+    delta = (input && input.delta) || 0;
+  }
+
+  deltas[node.inputPath_] = delta;
+  return delta;
+};
+
+// Growth is red and shrinkage green, more saturated the closer it gets to the biggest change.
+// A log scale keeps a few huge changes from washing out all the others.
+let assignColorsByDelta = (
+  colorMapping: ColorMapping,
+  deltas: Record<string, number>
+): void => {
+  let biggest = 0;
+  for (let path in deltas) {
+    if (path) biggest = Math.max(biggest, Math.abs(deltas[path]));
+  }
+  for (let path in deltas) {
+    let delta = deltas[path];
+    if (delta === 0) continue;
+    let t = Math.log1p(Math.abs(delta)) / Math.log1p(biggest);
+    let hue = delta > 0 ? 0 : 145;
+    colorMapping[path] =
+      "hsl(" + hue + ", " + Math.round(20 + 60 * t) + "%, " + Math.round(85 - 30 * t) + "%)";
+  }
+};
+
 export let colorLegendEl = document.createElement("div");
 let formatLegendHTML =
   "" +
@@ -276,7 +438,25 @@ let formatLegendHTML =
   cjsColor +
   '"></span>Ignored File<small>build artifacts, generated</small>' +
   `<span class="${styles.chit}" style="background:` +
+  untrackedColor +
+  '"></span>Untracked<small>not ignored, not committed</small>' +
+  `<span class="${styles.chit}" style="background:` +
+  gitColor +
+  '"></span>Git metadata<small>.git directories</small>' +
+  `<span class="${styles.chit}" style="background:` +
   otherColor +
   '"></span>Relocatable';
 
+let deltaLegendHTML =
+  "" +
+  `<span class="${styles.chit}" style="background:` +
+  grewColor +
+  '"></span>Grew<small>since the older snapshot</small>' +
+  `<span class="${styles.chit}" style="background:` +
+  shrankColor +
+  '"></span>Shrank<small>or deleted</small>' +
+  `<span class="${styles.chit}" style="background:` +
+  otherColor +
+  '"></span>Unchanged';
+
 colorLegendEl.id = styles.colorLegend;
@@ -6,8 +6,8 @@ import { accumulatePath, orderChildrenBySize, TreeNodeInProgress } from './tree'
 import { colorMode } from './index'
 import {
   canvasFillStyleForInputPath,
-  COLOR,
   colorLegendEl,
+  hasCategoryLabels,
   cssBackgroundForInputPath,
   moduleTypeLabelInputPath,
   otherColor,
@@ -307,7 +307,7 @@ export let createFlame = (metafile: Metafile): HTMLDivElement => {
 
     // Typeset the node size
     if (typesetX + ellipsisWidth < typesetW) {
-      sizeText = colorMode === COLOR.FORMAT ? moduleTypeLabelInputPath(node.inputPath_, ' – ') : node.sizeText_
+      sizeText = hasCategoryLabels(colorMode) ? moduleTypeLabelInputPath(node.inputPath_, ' – ') : node.sizeText_
       measuredW = c.measureText(sizeText).width
       if (typesetX + measuredW > typesetW) {
         sizeText = textOverflowEllipsis(sizeText, typesetW - typesetX)
@@ -449,7 +449,7 @@ export let createFlame = (metafile: Metafile): HTMLDivElement => {
       let tooltip = node.name_ === node.inputPath_ ? shortenDataURLForDisplay(node.inputPath_) : node.inputPath_
       let nameSplit = tooltip.length - node.name_.length
       tooltip = textToHTML(tooltip.slice(0, nameSplit)) + '<b>' + textToHTML(tooltip.slice(nameSplit)) + '</b>'
-      tooltip += colorMode === COLOR.FORMAT
+      tooltip += hasCategoryLabels(colorMode)
         ? textToHTML(moduleTypeLabelInputPath(node.inputPath_, ' – '))
         : ' – ' + textToHTML(bytesToText(node.bytesInOutput_))
       showTooltip(e.pageX, e.pageY + 20, tooltip)
@@ -152,8 +152,20 @@ export let finishLoading = (json: string): void => {
 
     chartMode = CHART.NONE;
     colorMode = COLOR.NONE;
+    // Clicking the summary cycles through the colorings, by file type only when duh sent types
+    // and by delta only when it compared two snapshots
+    let hasFileTypes = Object.values(metafile.inputs).some((input) => input.fileType);
+    let hasDeltas = Object.values(metafile.inputs).some((input) => input.delta !== undefined);
     showSummary(metafile, () =>
-      useColor(colorMode === COLOR.DIRECTORY ? COLOR.FORMAT : COLOR.DIRECTORY)
+      useColor(
+        colorMode === COLOR.DIRECTORY
+          ? COLOR.FORMAT
+          : colorMode === COLOR.FORMAT && hasFileTypes
+          ? COLOR.TYPE
+          : (colorMode === COLOR.FORMAT || colorMode === COLOR.TYPE) && hasDeltas
+          ? COLOR.DELTA
+          : COLOR.DIRECTORY
+      )
     );
     showWarningsPanel(metafile);
     hideWhyFile();
@@ -184,7 +196,7 @@ export let finishLoading = (json: string): void => {
         timeline.startClock("updateColorMapping");
         timeline.updateDisplay(timelineContent);
         requestAnimationFrame(() => {
-          useColor(COLOR.DIRECTORY);
+          useColor(hasDeltas ? COLOR.DELTA : COLOR.DIRECTORY);
           timeline.stopClock("updateColorMapping");
           console.info(timeline.toString());
           timeline.updateDisplay(timelineContent);
@@ -7,6 +7,13 @@ export interface InputFile {
   bytes: number
   imports: ImportRecord[]
   format?: 'cjs' | 'esm' | 'both'
+  // Bit set: 1 = ignored, 2 = not ignored (tracked or outside of git), 4 = untracked,
+  // 8 = git metadata
+  categories?: number
+  // The name of the file type, see `duh --by-type`
+  fileType?: string
+  // Bytes gained since the older snapshot, negative when they shrank, see `duh diff`
+  delta?: number
   with?: Record<string, string>
 }
 
@@ -1,6 +1,6 @@
 import * as styles from './summary.css'
 import { Metafile } from './metafile'
-import { cjsColor, esmColor } from './color'
+import { FORMATS, cjsColor, esmColor, formatsForInput, gitColor, untrackedColor } from './color'
 import {
   bytesToText,
   formatInteger,
@@ -24,16 +24,23 @@ export let showSummary = (metafile: Metafile, toggleColor: () => void): void =>
   let totalBytesOut = 0
   let esmByteCountIn = 0
   let cjsByteCountIn = 0
+  let gitByteCountIn = 0
+  let untrackedByteCountIn = 0
   let otherByteCountIn = 0
   let esmWidth: number
   let cjsWidth: number
+  let gitWidth: number
+  let untrackedWidth: number
   let formatBreakdownEl: HTMLAnchorElement | undefined
 
   for (let file in inputs) {
     let input = inputs[file]
-    let format = input.format
-    if (format === 'esm') esmByteCountIn += input.bytes
-    else if (format === 'cjs') cjsByteCountIn += input.bytes
+    // An input with several categories counts towards the one it is colored with first
+    let formats = formatsForInput(input)
+    if (formats & FORMATS.UNTRACKED) untrackedByteCountIn += input.bytes
+    else if (formats & FORMATS.CJS) cjsByteCountIn += input.bytes
+    else if (formats & FORMATS.GIT) gitByteCountIn += input.bytes
+    else if (formats & FORMATS.ESM) esmByteCountIn += input.bytes
     else otherByteCountIn += input.bytes
     fileCountIn++
     totalBytesIn += input.bytes
@@ -48,6 +55,8 @@ export let showSummary = (metafile: Metafile, toggleColor: () => void): void =>
 
   esmWidth = Math.round(CONSTANTS.FORMAT_WIDTH * esmByteCountIn / totalBytesIn)
   cjsWidth = Math.round(CONSTANTS.FORMAT_WIDTH * cjsByteCountIn / totalBytesIn)
+  gitWidth = Math.round(CONSTANTS.FORMAT_WIDTH * gitByteCountIn / totalBytesIn)
+  untrackedWidth = Math.round(CONSTANTS.FORMAT_WIDTH * untrackedByteCountIn / totalBytesIn)
 
   summaryPanel.innerHTML = ''
     + '<table><tr>'
@@ -60,20 +69,22 @@ export let showSummary = (metafile: Metafile, toggleColor: () => void): void =>
     + `<td class="${styles.symbol}">&rarr;</td>`
 
     + '<td>'
-    + '<h2>' + textToHTML(bytesToText(esmByteCountIn)) + '</h2>'
+    + '<h2>' + textToHTML(bytesToText(esmByteCountIn + untrackedByteCountIn)) + '</h2>'
     + 'non-ignored'
     // + textToHTML(formatInteger(fileCountOut)) + ' output ' + countedFiles(fileCountOut)
     + '</td>'
 
     + '</tr></table>'
 
-    + (esmByteCountIn || cjsByteCountIn
+    + (esmByteCountIn || cjsByteCountIn || gitByteCountIn || untrackedByteCountIn
       ? ''
       + `<a href="javascript:void 0" class="${styles.formatBreakdown}">`
       + `<span class="${styles.side}">` + formatInteger(Math.round(100 * cjsByteCountIn / totalBytesIn)) + '% Ignored</span>'
       + `<div class="${styles.bar}">`
       + '<div style="background:' + cjsColor + ';width:' + cjsWidth + 'px"></div>'
-      + '<div style="background:#CCC;width:' + (CONSTANTS.FORMAT_WIDTH - esmWidth - cjsWidth) + 'px"></div>'
+      + '<div style="background:' + gitColor + ';width:' + gitWidth + 'px"></div>'
+      + '<div style="background:#CCC;width:' + (CONSTANTS.FORMAT_WIDTH - esmWidth - cjsWidth - gitWidth - untrackedWidth) + 'px"></div>'
+      + '<div style="background:' + untrackedColor + ';width:' + untrackedWidth + 'px"></div>'
       + '<div style="background:' + esmColor + ';width:' + esmWidth + 'px"></div>'
       + '</div>'
       + `<span class="${styles.side}">` + formatInteger(Math.round(100 * esmByteCountIn / totalBytesIn)) + '% Essential</span>'
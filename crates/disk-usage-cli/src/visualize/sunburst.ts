@@ -10,8 +10,8 @@ import {
 import { colorMode, timeline } from "./index";
 import {
   canvasFillStyleForInputPath,
-  COLOR,
   colorLegendEl,
+  hasCategoryLabels,
   cssBackgroundForInputPath,
   moduleTypeLabelInputPath,
   setAfterColorMappingUpdate,
@@ -510,7 +510,7 @@ export let createSunburst = (metafile: Metafile): HTMLDivElement => {
           tooltip =
             "<b>" + textToHTML(shortenDataURLForDisplay(tooltip)) + "</b>";
         }
-        if (colorMode === COLOR.FORMAT)
+        if (hasCategoryLabels(colorMode))
           tooltip += textToHTML(
             moduleTypeLabelInputPath(node.inputPath_, " – ")
           );
@@ -711,7 +711,7 @@ export let createSunburst = (metafile: Metafile): HTMLDivElement => {
         let bytesEl = document.createElement("div");
         bytesEl.className = styles.last;
         bytesEl.textContent =
-          colorMode === COLOR.FORMAT
+          hasCategoryLabels(colorMode)
             ? moduleTypeLabelInputPath(child.inputPath_, "")
             : size;
         barEl.append(bytesEl);
@@ -9,9 +9,9 @@ import {
 import { isWhyFileVisible, showWhyFile } from "./whyfile";
 import { colorMode, timeline } from "./index";
 import {
-  COLOR,
   canvasFillStyleForInputPath,
   colorLegendEl,
+  hasCategoryLabels,
   cssBackgroundForInputPath,
   moduleTypeLabelInputPath,
   setAfterColorMappingUpdate,
@@ -551,7 +551,7 @@ export let createTreemap = (metafile: Metafile): HTMLDivElement => {
       if (nameText === node.name_ && node.sortedChildren_.length) {
         let detailText =
           " – " +
-          (colorMode === COLOR.FORMAT
+          (hasCategoryLabels(colorMode)
             ? moduleTypeLabelInputPath(node.inputPath_, "")
             : node.sizeText_);
         let [sizeText, sizeWidth] = textOverflowEllipsis(
@@ -584,7 +584,7 @@ export let createTreemap = (metafile: Metafile): HTMLDivElement => {
       // Draw the node detail (only if there's enough space and only for leaf nodes)
       if (h > CONSTANTS.INSET_Y + 16 && !node.sortedChildren_.length) {
         let detailText =
-          colorMode === COLOR.FORMAT
+          hasCategoryLabels(colorMode)
             ? moduleTypeLabelInputPath(node.inputPath_, "")
             : node.sizeText_;
         let [sizeText, sizeWidth] = textOverflowEllipsis(detailText, maxWidth);
@@ -751,7 +751,7 @@ export let createTreemap = (metafile: Metafile): HTMLDivElement => {
         textToHTML(tooltip.slice(nameSplit)) +
         "</b>";
       tooltip +=
-        colorMode === COLOR.FORMAT
+        hasCategoryLabels(colorMode)
           ? textToHTML(moduleTypeLabelInputPath(node.inputPath_, " – "))
           : " – " + textToHTML(bytesToText(node.bytesInOutput_));
       showTooltip(e.pageX, e.pageY + 20, tooltip);
@@ -0,0 +1,173 @@
+// showing disk usage with .gitignore awareness
+// Copyright (C) 2024 Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::output::{self, Output};
+use crate::{describe_ignore_reason, glob_args, walk_builder, Globs};
+use clap::{Arg, ArgAction, ArgMatches};
+use ignore::{Explanation, Match};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub fn command(args: impl IntoIterator<Item = Arg>) -> clap::Command {
+    clap::Command::new("why")
+        .about("Explain why each path is or isn't ignored, like `git check-ignore -v`")
+        .long_about("Explain why each path is or isn't ignored, like `git check-ignore -v` but without needing git. Prints the rule that decided the outcome, any whitelist rules it overrode, and whether the path is only ignored because a parent directory is ignored.")
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .default_value("./")
+                .action(ArgAction::Set)
+                .help("The path duh would be scanning. Ignore files are read from here and its parent directories."),
+        )
+        .arg(
+            Arg::new("hidden")
+                .short('H')
+                .long("hidden")
+                .action(ArgAction::SetTrue)
+                .help("Same as --hidden when scanning"),
+        )
+        .args(glob_args())
+        .args(args)
+        .arg(
+            Arg::new("PATH")
+                .required(true)
+                .action(ArgAction::Append)
+                .help("The paths to explain"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) {
+    let root = matches.get_one::<String>("root").unwrap();
+    let builder = walk_builder(root, matches.get_flag("hidden"), &Globs::parse(matches));
+    let mut out = Output::new(matches.get_one::<PathBuf>("output").map(PathBuf::as_path));
+    let mut failed = false;
+    for path in matches.get_many::<String>("PATH").unwrap_or_default() {
+        let explanation = resolve(Path::new(root), Path::new(path))
+            .and_then(|path| builder.explain(path).map_err(|err| err.to_string()));
+        match explanation {
+            Ok(explanation) => {
+                if let Err(err) = print_explanation(&explanation, &mut out) {
+                    output::exit_on_error(err);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                failed = true;
+            }
+        }
+    }
+    if let Err(err) = out.flush() {
+        output::exit_on_error(err);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Spell `path` the way the walker would, i.e. as `root` joined with the path relative to
+/// it, so that relative paths work no matter which directory they are relative to.
+fn resolve(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let absolute_root = root.canonicalize().map_err(|err| format!("{}: {}", root.display(), err))?;
+    let absolute_path = path.canonicalize().map_err(|err| err.to_string())?;
+    match absolute_path.strip_prefix(&absolute_root) {
+        Ok(relative) => Ok(root.join(relative)),
+        Err(_) => Err(format!("not inside {}", root.display())),
+    }
+}
+
+fn print_explanation(explanation: &Explanation, out: &mut impl Write) -> io::Result<()> {
+    let path = explanation.path().display();
+    let reason = match explanation.ignore_reason() {
+        None => {
+            writeln!(out, "{}: not ignored", path)?;
+            match explanation.matches().first() {
+                None => writeln!(out, "    no rule matches")?,
+                Some(winner) => {
+                    writeln!(out, "    whitelisted by {}", describe_match(winner))?;
+                    for m in &explanation.matches()[1..] {
+                        if m.is_ignore() {
+                            writeln!(out, "    which overrides {}", describe_match(m))?;
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Some(reason) => reason,
+    };
+
+    writeln!(out, "{}: ignored", path)?;
+    match explanation.ignored_parent() {
+        Some(parent) => {
+            // Walk::next never looks at the rules of anything below an ignored directory.
+            writeln!(
+                out,
+                "    because parent directory {} is ignored by {}",
+                parent.display(),
+                describe_ignore_reason(reason)
+            )?;
+            for m in explanation.matches() {
+                if m.is_whitelist() {
+                    writeln!(out, "    which overrides whitelist {}", describe_match(m))?;
+                } else {
+                    writeln!(out, "    also matches {}", describe_match(m))?;
+                }
+            }
+        }
+        None => {
+            writeln!(out, "    by {}", describe_ignore_reason(reason))?;
+            for m in explanation.matches() {
+                if m.is_whitelist() {
+                    writeln!(out, "    which overrides whitelist {}", describe_match(m))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn describe_match(m: &Match<ignore::IgnoreReason>) -> String {
+    m.inner().map(describe_ignore_reason).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::print_explanation;
+    use crate::tests::TempDir;
+    use crate::{walk_builder, Globs};
+
+    fn why(td: &TempDir, path: &str) -> String {
+        let root = td.path().to_str().unwrap();
+        let builder = walk_builder(root, false, &Globs::default());
+        let explanation = builder.explain(td.path().join(path)).unwrap();
+        let mut out = Vec::new();
+        print_explanation(&explanation, &mut out).unwrap();
+        String::from_utf8(out).unwrap().replace(root, "")
+    }
+
+    #[test]
+    fn ignored_and_whitelisted() {
+        let td = TempDir::new();
+        td.write(".git/HEAD", b"ref: refs/heads/main\n");
+        td.write(".gitignore", b"*.log\n!keep.log\n");
+        td.write("a.log", b"");
+        td.write("keep.log", b"");
+
+        assert_eq!(why(&td, "a.log"), "/a.log: ignored\n    by `*.log` in /.gitignore:1\n");
+        assert_eq!(
+            why(&td, "keep.log"),
+            "/keep.log: not ignored\n    whitelisted by `!keep.log` in /.gitignore:2\n    which overrides `*.log` in /.gitignore:1\n"
+        );
+    }
+}
@@ -34,13 +34,11 @@ use crate::{
 /// IgnoreMatch represents information about where a match came from when using
 /// the `Ignore` matcher.
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 pub(crate) struct IgnoreMatch<'a>(IgnoreMatchInner<'a>);
 
 /// IgnoreMatchInner describes precisely where the match information came from.
 /// This is private to allow expansion to more matchers in the future.
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 enum IgnoreMatchInner<'a> {
     Override(overrides::Glob<'a>),
     Gitignore(&'a gitignore::Glob),
@@ -64,6 +62,116 @@ impl<'a> IgnoreMatch<'a> {
     fn hidden() -> IgnoreMatch<'static> {
         IgnoreMatch(IgnoreMatchInner::Hidden)
     }
+
+    /// Copy the information about this match out of the matcher, so that it
+    /// can outlive the matcher.
+    pub(crate) fn to_reason(&self) -> IgnoreReason {
+        match self.0 {
+            IgnoreMatchInner::Override(ref glob) => match glob.glob() {
+                Some(glob) => IgnoreReason::from_glob(IgnoreKind::Override, glob),
+                None => IgnoreReason::new(IgnoreKind::Override),
+            },
+            IgnoreMatchInner::Gitignore(glob) => {
+                IgnoreReason::from_glob(IgnoreKind::Gitignore, glob)
+            }
+            IgnoreMatchInner::Types(ref glob) => IgnoreReason {
+                pattern: glob.file_type_def().map(|def| def.name().to_string()),
+                ..IgnoreReason::new(IgnoreKind::Types)
+            },
+            IgnoreMatchInner::Hidden => IgnoreReason::new(IgnoreKind::Hidden),
+        }
+    }
+}
+
+/// The kind of rule that caused a directory entry to be ignored.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum IgnoreKind {
+    /// A glob from a file in gitignore format. That is `.gitignore`,
+    /// `.ignore`, `.git/info/exclude`, the global gitignore file, custom ignore
+    /// files and explicitly added ignore files.
+    Gitignore,
+    /// A glob override. If there is at least one whitelist override, then
+    /// files that don't match any override are ignored too.
+    Override,
+    /// A file type selection. If at least one file type is selected, then
+    /// files that don't match any selected type are ignored too.
+    Types,
+    /// The file or directory is hidden.
+    Hidden,
+    /// The predicate given to `WalkBuilder::filter_entry` returned false.
+    Filter,
+    /// The file is larger than the limit set by `WalkBuilder::max_filesize`.
+    MaxFilesize,
+    /// The file is the same file as stdout. See `WalkBuilder::skip_stdout`.
+    Stdout,
+}
+
+/// IgnoreReason describes the rule that caused a directory entry to be
+/// ignored.
+///
+/// Unlike the matchers it was produced by, this is an owned value. Every
+/// entry below an ignored directory shares the reason of that directory.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct IgnoreReason {
+    kind: IgnoreKind,
+    from: Option<PathBuf>,
+    line: Option<u64>,
+    pattern: Option<String>,
+}
+
+impl IgnoreReason {
+    pub(crate) fn new(kind: IgnoreKind) -> IgnoreReason {
+        IgnoreReason { kind, from: None, line: None, pattern: None }
+    }
+
+    fn from_glob(kind: IgnoreKind, glob: &gitignore::Glob) -> IgnoreReason {
+        IgnoreReason {
+            kind,
+            from: glob.from().map(Path::to_path_buf),
+            line: glob.line(),
+            pattern: Some(glob.original().to_string()),
+        }
+    }
+
+    /// The kind of rule.
+    pub fn kind(&self) -> IgnoreKind {
+        self.kind
+    }
+
+    /// The file that defined the rule, if the rule came from a file.
+    pub fn from(&self) -> Option<&Path> {
+        self.from.as_deref()
+    }
+
+    /// The line number, starting at 1, of the rule in the file returned by
+    /// `from`.
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    /// The rule as written by the user.
+    ///
+    /// For globs this is the original glob, e.g., `/target`. For file types
+    /// this is the name of the file type. This is `None` for rules that do
+    /// not have a pattern, such as hidden files, or an override or file type
+    /// selection that ignored a file because nothing matched it.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+}
+
+/// The kinds of ignore files, in order of decreasing precedence.
+///
+/// Note that precedence between kinds is not impacted by the directory
+/// hierarchy. Any `.ignore` file overrides all `.gitignore` files.
+#[derive(Clone, Copy, Debug)]
+enum IgnoreFile {
+    Custom,
+    Ignore,
+    Git,
+    GitExclude,
+    GitGlobal,
+    Explicit,
 }
 
 /// Options for the ignore matcher, shared between the matcher itself and the
@@ -408,6 +516,53 @@ impl Ignore {
         whitelisted
     }
 
+    /// Returns every rule that matches the given file path, in order of
+    /// decreasing precedence.
+    ///
+    /// Unlike `matched`, this does not stop at the first match. It is meant
+    /// for explaining a match to a human, not for walking a directory.
+    pub(crate) fn matched_all<'a>(
+        &'a self,
+        path: &Path,
+        is_dir: bool,
+    ) -> Vec<Match<IgnoreMatch<'a>>> {
+        let mut path = path;
+        if let Some(p) = strip_prefix("./", path) {
+            path = p;
+        }
+        let mut all = vec![];
+        if !self.0.overrides.is_empty() {
+            let mat = self
+                .0
+                .overrides
+                .matched(path, is_dir)
+                .map(IgnoreMatch::overrides);
+            if !mat.is_none() {
+                all.push(mat);
+            }
+        }
+        if self.has_any_ignore_rules() {
+            let mut by_file: [Vec<Match<IgnoreMatch<'a>>>; 6] =
+                Default::default();
+            self.visit_ignore_matchers(path, |file, gi, path| {
+                by_file[file as usize].extend(
+                    gi.matched_all(path, is_dir)
+                        .into_iter()
+                        .map(|m| m.map(IgnoreMatch::gitignore)),
+                );
+            });
+            all.extend(by_file.into_iter().flatten());
+        }
+        if !self.0.types.is_empty() {
+            let mat =
+                self.0.types.matched(path, is_dir).map(IgnoreMatch::types);
+            if !mat.is_none() {
+                all.push(mat);
+            }
+        }
+        all
+    }
+
     /// Performs matching only on the ignore files for this directory and
     /// all parent directories.
     fn matched_ignore<'a>(
@@ -420,35 +575,57 @@ impl Ignore {
             mut m_ignore,
             mut m_gi,
             mut m_gi_exclude,
+            mut m_global,
             mut m_explicit,
-        ) = (Match::None, Match::None, Match::None, Match::None, Match::None);
+        ) = (
+            Match::None,
+            Match::None,
+            Match::None,
+            Match::None,
+            Match::None,
+            Match::None,
+        );
+        self.visit_ignore_matchers(path, |file, gi, path| {
+            let m = match file {
+                IgnoreFile::Custom => &mut m_custom_ignore,
+                IgnoreFile::Ignore => &mut m_ignore,
+                IgnoreFile::Git => &mut m_gi,
+                IgnoreFile::GitExclude => &mut m_gi_exclude,
+                IgnoreFile::GitGlobal => &mut m_global,
+                IgnoreFile::Explicit => &mut m_explicit,
+            };
+            if m.is_none() {
+                *m = gi.matched(path, is_dir).map(IgnoreMatch::gitignore);
+            }
+        });
+
+        m_custom_ignore
+            .or(m_ignore)
+            .or(m_gi)
+            .or(m_gi_exclude)
+            .or(m_global)
+            .or(m_explicit)
+    }
+
+    /// Calls `visit` with every ignore file matcher that applies to `path`,
+    /// along with the path it should be matched against.
+    ///
+    /// For each kind of ignore file, matchers are visited in order of
+    /// decreasing precedence, i.e., the matcher of the most nested directory
+    /// comes first. The precedence between kinds is given by `IgnoreFile`.
+    fn visit_ignore_matchers<'a, F>(&'a self, path: &Path, mut visit: F)
+    where
+        F: FnMut(IgnoreFile, &'a Gitignore, &Path),
+    {
         let any_git =
             !self.0.opts.require_git || self.parents().any(|ig| ig.0.has_git);
         let mut saw_git = false;
         for ig in self.parents().take_while(|ig| !ig.0.is_absolute_parent) {
-            if m_custom_ignore.is_none() {
-                m_custom_ignore =
-                    ig.0.custom_ignore_matcher
-                        .matched(path, is_dir)
-                        .map(IgnoreMatch::gitignore);
-            }
-            if m_ignore.is_none() {
-                m_ignore =
-                    ig.0.ignore_matcher
-                        .matched(path, is_dir)
-                        .map(IgnoreMatch::gitignore);
-            }
-            if any_git && !saw_git && m_gi.is_none() {
-                m_gi =
-                    ig.0.git_ignore_matcher
-                        .matched(path, is_dir)
-                        .map(IgnoreMatch::gitignore);
-            }
-            if any_git && !saw_git && m_gi_exclude.is_none() {
-                m_gi_exclude =
-                    ig.0.git_exclude_matcher
-                        .matched(path, is_dir)
-                        .map(IgnoreMatch::gitignore);
+            visit(IgnoreFile::Custom, &ig.0.custom_ignore_matcher, path);
+            visit(IgnoreFile::Ignore, &ig.0.ignore_matcher, path);
+            if any_git && !saw_git {
+                visit(IgnoreFile::Git, &ig.0.git_ignore_matcher, path);
+                visit(IgnoreFile::GitExclude, &ig.0.git_exclude_matcher, path);
             }
             saw_git = saw_git || ig.0.has_git;
         }
@@ -480,55 +657,26 @@ impl Ignore {
                 for ig in
                     self.parents().skip_while(|ig| !ig.0.is_absolute_parent)
                 {
-                    if m_custom_ignore.is_none() {
-                        m_custom_ignore =
-                            ig.0.custom_ignore_matcher
-                                .matched(&path, is_dir)
-                                .map(IgnoreMatch::gitignore);
-                    }
-                    if m_ignore.is_none() {
-                        m_ignore =
-                            ig.0.ignore_matcher
-                                .matched(&path, is_dir)
-                                .map(IgnoreMatch::gitignore);
-                    }
-                    if any_git && !saw_git && m_gi.is_none() {
-                        m_gi =
-                            ig.0.git_ignore_matcher
-                                .matched(&path, is_dir)
-                                .map(IgnoreMatch::gitignore);
-                    }
-                    if any_git && !saw_git && m_gi_exclude.is_none() {
-                        m_gi_exclude =
-                            ig.0.git_exclude_matcher
-                                .matched(&path, is_dir)
-                                .map(IgnoreMatch::gitignore);
+                    visit(IgnoreFile::Custom, &ig.0.custom_ignore_matcher, &path);
+                    visit(IgnoreFile::Ignore, &ig.0.ignore_matcher, &path);
+                    if any_git && !saw_git {
+                        visit(IgnoreFile::Git, &ig.0.git_ignore_matcher, &path);
+                        visit(
+                            IgnoreFile::GitExclude,
+                            &ig.0.git_exclude_matcher,
+                            &path,
+                        );
                     }
                     saw_git = saw_git || ig.0.has_git;
                 }
             }
         }
         for gi in self.0.explicit_ignores.iter().rev() {
-            if !m_explicit.is_none() {
-                break;
-            }
-            m_explicit = gi.matched(&path, is_dir).map(IgnoreMatch::gitignore);
+            visit(IgnoreFile::Explicit, gi, path);
+        }
+        if any_git {
+            visit(IgnoreFile::GitGlobal, &self.0.git_global_matcher, path);
         }
-        let m_global = if any_git {
-            self.0
-                .git_global_matcher
-                .matched(&path, is_dir)
-                .map(IgnoreMatch::gitignore)
-        } else {
-            Match::None
-        };
-
-        m_custom_ignore
-            .or(m_ignore)
-            .or(m_gi)
-            .or(m_gi_exclude)
-            .or(m_global)
-            .or(m_explicit)
     }
 
     /// Returns an iterator over parent ignore matchers, including this one.
@@ -820,15 +968,14 @@ pub(crate) fn create_gitignore<T: AsRef<OsStr>>(
     (gi, errs.into_error_option())
 }
 
-/// Find the GIT_COMMON_DIR for the given git worktree.
+/// Find the git directory of the given git worktree.
 ///
-/// This is the directory that may contain a private ignore file
-/// "info/exclude". Unlike git, this function does *not* read environment
-/// variables GIT_DIR and GIT_COMMON_DIR, because it is not clear how to use
-/// them when multiple repositories are searched.
+/// `.git` is either the git directory itself, or a file with a `gitdir: `
+/// line pointing to it, which is how linked worktrees and submodules are set
+/// up. A relative `gitdir` is relative to `dir`.
 ///
 /// Some I/O errors are ignored.
-fn resolve_git_commondir(
+pub fn resolve_git_dir(
     dir: &Path,
     git_type: Option<FileType>,
 ) -> Result<PathBuf, Option<Error>> {
@@ -853,7 +1000,25 @@ fn resolve_git_commondir(
     if !dot_git_line.starts_with("gitdir: ") {
         return Err(None);
     }
-    let real_git_dir = PathBuf::from(&dot_git_line["gitdir: ".len()..]);
+    Ok(dir.join(&dot_git_line["gitdir: ".len()..]))
+}
+
+/// Find the GIT_COMMON_DIR for the given git worktree.
+///
+/// This is the directory that may contain a private ignore file
+/// "info/exclude". Unlike git, this function does *not* read environment
+/// variables GIT_DIR and GIT_COMMON_DIR, because it is not clear how to use
+/// them when multiple repositories are searched.
+///
+/// Some I/O errors are ignored.
+pub fn resolve_git_commondir(
+    dir: &Path,
+    git_type: Option<FileType>,
+) -> Result<PathBuf, Option<Error>> {
+    if !git_type.is_some_and(|ft| ft.is_file()) {
+        return Ok(dir.join(".git"));
+    }
+    let real_git_dir = resolve_git_dir(dir, git_type)?;
     let git_commondir_file = || real_git_dir.join("commondir");
     let file = match File::open(git_commondir_file()) {
         Ok(file) => io::BufReader::new(file),
@@ -32,6 +32,8 @@ use crate::{
 pub struct Glob {
     /// The file path that this glob was extracted from.
     from: Option<PathBuf>,
+    /// The line number (starting at 1) of this glob in `from`.
+    line: Option<u64>,
     /// The original glob string.
     original: String,
     /// The actual glob string used to convert to a regex.
@@ -48,6 +50,15 @@ impl Glob {
         self.from.as_ref().map(|p| &**p)
     }
 
+    /// Returns the line number, starting at 1, of this glob in the file
+    /// returned by `from`.
+    ///
+    /// This is only known for globs read from a file with
+    /// `GitignoreBuilder::add`.
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
     /// The original glob as it was defined in a gitignore file.
     pub fn original(&self) -> &str {
         &self.original
@@ -196,6 +207,39 @@ impl Gitignore {
         self.matched_stripped(self.strip(path.as_ref()), is_dir)
     }
 
+    /// Returns every glob in this gitignore matcher that matches the given
+    /// path, in order of decreasing precedence. The first element, if any, is
+    /// the same match returned by `matched`.
+    ///
+    /// `is_dir` should be true if the path refers to a directory and false
+    /// otherwise.
+    pub fn matched_all<P: AsRef<Path>>(
+        &self,
+        path: P,
+        is_dir: bool,
+    ) -> Vec<Match<&Glob>> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let path = self.strip(path.as_ref());
+        let mut matches = self.matches.as_ref().unwrap().get();
+        let candidate = Candidate::new(path);
+        self.set.matches_candidate_into(&candidate, &mut matches);
+        matches
+            .iter()
+            .rev()
+            .map(|&i| &self.globs[i])
+            .filter(|glob| !glob.is_only_dir() || is_dir)
+            .map(|glob| {
+                if glob.is_whitelist() {
+                    Match::Whitelist(glob)
+                } else {
+                    Match::Ignore(glob)
+                }
+            })
+            .collect()
+    }
+
     /// Returns whether the given path (file or directory, and expected to be
     /// under the root) or any of its parent directories (up to the root)
     /// matched a pattern in this gitignore matcher.
@@ -401,7 +445,9 @@ impl GitignoreBuilder {
                     break;
                 }
             };
-            if let Err(err) = self.add_line(Some(path.to_path_buf()), &line) {
+            if let Err(err) =
+                self.add_glob(Some(path.to_path_buf()), Some(lineno), &line)
+            {
                 errs.push(err.tagged(path, lineno));
             }
         }
@@ -435,6 +481,16 @@ impl GitignoreBuilder {
     pub fn add_line(
         &mut self,
         from: Option<PathBuf>,
+        line: &str,
+    ) -> Result<&mut GitignoreBuilder, Error> {
+        self.add_glob(from, None, line)
+    }
+
+    /// Like `add_line`, but also records the line number the glob came from.
+    fn add_glob(
+        &mut self,
+        from: Option<PathBuf>,
+        lineno: Option<u64>,
         mut line: &str,
     ) -> Result<&mut GitignoreBuilder, Error> {
         #![allow(deprecated)]
@@ -450,6 +506,7 @@ impl GitignoreBuilder {
         }
         let mut glob = Glob {
             from,
+            line: lineno,
             original: line.to_string(),
             actual: String::new(),
             is_whitelist: false,
@@ -639,6 +696,7 @@ mod tests {
     use std::path::Path;
 
     use super::{Gitignore, GitignoreBuilder};
+    use crate::Match;
 
     fn gi_from_str<P: AsRef<Path>>(root: P, s: &str) -> Gitignore {
         let mut builder = GitignoreBuilder::new(root);
@@ -809,6 +867,33 @@ mod tests {
         assert!(!gi.matched("foo.HTM", false).is_ignore());
     }
 
+    #[test]
+    fn line_numbers() {
+        let td = crate::tests::TempDir::new().unwrap();
+        let path = td.path().join(".gitignore");
+        std::fs::write(&path, "# build output\n\n/target\n!keep.log\n*.log\n")
+            .unwrap();
+        let mut builder = GitignoreBuilder::new(td.path());
+        assert!(builder.add(&path).is_none());
+        let gi = builder.build().unwrap();
+
+        let glob = match gi.matched(td.path().join("target"), true) {
+            Match::Ignore(glob) => glob,
+            m => panic!("expected ignore match but got {:?}", m),
+        };
+        assert_eq!(glob.from(), Some(path.as_path()));
+        assert_eq!(glob.line(), Some(3));
+        assert_eq!(glob.original(), "/target");
+
+        let glob = gi.matched(td.path().join("debug.log"), false);
+        assert_eq!(glob.inner().and_then(|g| g.line()), Some(5));
+        let glob = gi.matched(td.path().join("keep.log"), false);
+        assert!(glob.is_ignore());
+
+        let gi = gi_from_str(ROOT, "*.log");
+        assert_eq!(gi.matched("foo.log", false).inner().unwrap().line(), None);
+    }
+
     ignored!(cs1, ROOT, "*.html", "foo.html");
     not_ignored!(cs2, ROOT, "*.html", "foo.HTML");
     not_ignored!(cs3, ROOT, "*.html", "foo.htm");
@@ -48,9 +48,12 @@ See the documentation for `WalkBuilder` for many other options.
 
 use std::path::{Path, PathBuf};
 
+pub use crate::dir::{
+    resolve_git_commondir, resolve_git_dir, IgnoreKind, IgnoreReason,
+};
 pub use crate::walk::{
-    DirEntry, ParallelVisitor, ParallelVisitorBuilder, Walk, WalkBuilder,
-    WalkParallel, WalkState,
+    DirEntry, Explanation, ParallelVisitor, ParallelVisitorBuilder, Walk,
+    WalkBuilder, WalkParallel, WalkState,
 };
 
 mod default_types;
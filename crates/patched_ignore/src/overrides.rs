@@ -39,6 +39,14 @@ impl<'a> Glob<'a> {
     fn unmatched() -> Glob<'a> {
         Glob(GlobInner::UnmatchedIgnore)
     }
+
+    /// Return the override glob that matched, if one exists.
+    pub(crate) fn glob(&self) -> Option<&'a gitignore::Glob> {
+        match self.0 {
+            GlobInner::UnmatchedIgnore => None,
+            GlobInner::Matched(glob) => Some(glob),
+        }
+    }
 }
 
 /// Manages a set of overrides provided explicitly by the end user.
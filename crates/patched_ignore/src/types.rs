@@ -129,7 +129,9 @@ impl<'a> Glob<'a> {
     /// Return the file type definition that matched, if one exists. A file type
     /// definition always exists when a specific definition matches a file
     /// path.
-    pub fn file_type_def(&self) -> Option<&FileTypeDef> {
+    ///
+    /// The definition borrows from the file type matcher, not from this glob.
+    pub fn file_type_def(&self) -> Option<&'a FileTypeDef> {
         match self {
             Glob(GlobInner::UnmatchedIgnore) => None,
             Glob(GlobInner::Matched { def, .. }) => Some(def),
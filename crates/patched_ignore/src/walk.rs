@@ -15,11 +15,11 @@ use {
 };
 
 use crate::{
-    dir::{Ignore, IgnoreBuilder},
+    dir::{Ignore, IgnoreBuilder, IgnoreKind, IgnoreReason},
     gitignore::GitignoreBuilder,
     overrides::Override,
     types::Types,
-    Error, PartialErrorBuilder,
+    Error, Match, PartialErrorBuilder,
 };
 
 /// A directory entry with a possible error attached.
@@ -33,6 +33,9 @@ pub struct DirEntry {
     /// Did this path match an ignore rule? Useful to find the list of all files not ignored by git
     /// for example.
     pub ignored: bool,
+    /// The rule that ignored this path, or the rule that ignored the closest
+    /// ignored parent directory.
+    reason: Option<Arc<IgnoreReason>>,
 }
 
 const IGNORED: bool = true;
@@ -95,6 +98,15 @@ impl DirEntry {
         self.dent.ino()
     }
 
+    /// Returns the rule that caused this entry to be ignored.
+    ///
+    /// Entries inside an ignored directory are not matched against any rules,
+    /// so they report the rule that ignored that directory. This is `None` if
+    /// and only if the entry is not ignored.
+    pub fn ignore_reason(&self) -> Option<&IgnoreReason> {
+        self.reason.as_deref()
+    }
+
     /// Returns an error, if one exists, associated with processing this entry.
     ///
     /// An example of an error is one that occurred while parsing an ignore
@@ -109,16 +121,22 @@ impl DirEntry {
         self.dent.is_dir()
     }
 
+    /// Marks this entry as ignored because of the given rule.
+    fn set_ignored(&mut self, reason: Arc<IgnoreReason>) {
+        self.ignored = IGNORED;
+        self.reason = Some(reason);
+    }
+
     fn new_stdin() -> DirEntry {
-        DirEntry { dent: DirEntryInner::Stdin, err: None, ignored: NOT_IGNORED }
+        DirEntry { dent: DirEntryInner::Stdin, err: None, ignored: NOT_IGNORED, reason: None }
     }
 
-    fn new_walkdir(dent: walkdir::DirEntry, err: Option<Error>, ignored: bool) -> DirEntry {
-        DirEntry { dent: DirEntryInner::Walkdir(dent), err, ignored }
+    fn new_walkdir(dent: walkdir::DirEntry, err: Option<Error>) -> DirEntry {
+        DirEntry { dent: DirEntryInner::Walkdir(dent), err, ignored: NOT_IGNORED, reason: None }
     }
 
-    fn new_raw(dent: DirEntryRaw, err: Option<Error>, ignored: bool) -> DirEntry {
-        DirEntry { dent: DirEntryInner::Raw(dent), err, ignored }
+    fn new_raw(dent: DirEntryRaw, err: Option<Error>) -> DirEntry {
+        DirEntry { dent: DirEntryInner::Raw(dent), err, ignored: NOT_IGNORED, reason: None }
     }
 }
 
@@ -616,6 +634,70 @@ impl WalkBuilder {
         }
     }
 
+    /// Explain whether the given path would be ignored by a walk built from
+    /// this builder, and which rules were involved.
+    ///
+    /// The path must exist and must be inside one of the paths given to this
+    /// builder. Every directory between the walked path and the given path is
+    /// checked the same way `Walk` would check it, including the rule that
+    /// once a directory is ignored, everything below it is ignored too.
+    pub fn explain<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Explanation, Error> {
+        let path = path.as_ref();
+        let (root, relative) = self
+            .paths
+            .iter()
+            .filter_map(|root| {
+                path.strip_prefix(root).ok().map(|rel| (root, rel))
+            })
+            .max_by_key(|(root, _)| root.components().count())
+            .ok_or_else(|| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "path is not inside any of the paths being walked",
+                ))
+                .with_path(path)
+            })?;
+        let mut walk = self.build();
+        if root.is_dir() {
+            walk.ig = walk.ig_root.add_parents(root).0.add_child(root).0;
+        }
+        let mut explanation = Explanation {
+            path: path.to_path_buf(),
+            reason: None,
+            ignored_parent: None,
+            matches: vec![],
+        };
+        let mut current = root.to_path_buf();
+        let last = relative.components().count();
+        for (depth, component) in relative.components().enumerate() {
+            current.push(component);
+            let raw =
+                DirEntryRaw::from_path(depth + 1, current.clone(), false)?;
+            let ent = DirEntry::new_raw(raw, None);
+            if depth + 1 == last {
+                explanation.matches = walk
+                    .ig
+                    .matched_all(ent.path(), ent.is_dir())
+                    .into_iter()
+                    .map(|m| m.map(|m| m.to_reason()))
+                    .collect();
+            }
+            if explanation.reason.is_none() {
+                explanation.reason = walk.skip_entry(&ent)?;
+                if explanation.reason.is_some() && depth + 1 != last {
+                    explanation.ignored_parent = Some(current.clone());
+                }
+            }
+            if ent.is_dir() {
+                walk.ig = walk.ig.add_child(ent.path()).0;
+            }
+        }
+        Ok(explanation)
+    }
+
     /// Add a file path to the iterator.
     ///
     /// Each additional file path added is traversed recursively. This should
@@ -913,6 +995,56 @@ impl WalkBuilder {
     }
 }
 
+/// Explanation describes why a single path is or isn't ignored.
+///
+/// It is created by `WalkBuilder::explain`.
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    path: PathBuf,
+    reason: Option<IgnoreReason>,
+    ignored_parent: Option<PathBuf>,
+    matches: Vec<Match<IgnoreReason>>,
+}
+
+impl Explanation {
+    /// The path that was explained.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether a walk would report this path as ignored.
+    pub fn is_ignored(&self) -> bool {
+        self.reason.is_some()
+    }
+
+    /// The rule that ignored this path, or the rule that ignored the closest
+    /// ignored parent directory. This is the same reason a walk would report
+    /// with `DirEntry::ignore_reason`.
+    pub fn ignore_reason(&self) -> Option<&IgnoreReason> {
+        self.reason.as_ref()
+    }
+
+    /// The closest parent directory that is ignored, if any.
+    ///
+    /// When a directory is ignored, a walk never consults the rules for the
+    /// entries below it. In that case the path is ignored even if one of its
+    /// own rules is a whitelist.
+    pub fn ignored_parent(&self) -> Option<&Path> {
+        self.ignored_parent.as_deref()
+    }
+
+    /// Every ignore file glob, override and file type that matches the path
+    /// itself, in order of decreasing precedence.
+    ///
+    /// When no parent directory is ignored, the first element decides the
+    /// outcome and any whitelist after it was overridden. Rules that do not
+    /// come from a matcher, such as hidden files or the filter given to
+    /// `WalkBuilder::filter_entry`, are only reported by `ignore_reason`.
+    pub fn matches(&self) -> &[Match<IgnoreReason>] {
+        &self.matches
+    }
+}
+
 /// Walk is a recursive directory iterator over file paths in one or more
 /// directories.
 ///
@@ -939,9 +1071,9 @@ impl Walk {
         WalkBuilder::new(path).build()
     }
 
-    fn skip_entry(&self, ent: &DirEntry) -> Result<bool, Error> {
+    fn skip_entry(&self, ent: &DirEntry) -> Result<Option<IgnoreReason>, Error> {
         if ent.depth() == 0 {
-            return Ok(false);
+            return Ok(None);
         }
         // We ensure that trivial skipping is done before any other potentially
         // expensive operations (stat, filesystem other) are done. This seems
@@ -952,27 +1084,29 @@ impl Walk {
         // when particular filesystem operations occurred. Users of this system
         // who ensured correct file-type filters were being used could still
         // get unnecessary file access resulting in large downloads.
-        if should_skip_entry(&self.ig, ent) {
-            return Ok(true);
+        if let Some(reason) = should_skip_entry(&self.ig, ent) {
+            return Ok(Some(reason));
         }
         if let Some(ref stdout) = self.skip {
             if path_equals(ent, stdout)? {
-                return Ok(true);
+                return Ok(Some(IgnoreReason::new(IgnoreKind::Stdout)));
             }
         }
         if self.max_filesize.is_some() && !ent.is_dir() {
-            return Ok(skip_filesize(
+            let too_big = skip_filesize(
                 self.max_filesize.unwrap(),
                 ent.path(),
                 &ent.metadata().ok(),
-            ));
+            );
+            return Ok(too_big
+                .then(|| IgnoreReason::new(IgnoreKind::MaxFilesize)));
         }
         if let Some(Filter(filter)) = &self.filter {
             if !filter(ent) {
-                return Ok(true);
+                return Ok(Some(IgnoreReason::new(IgnoreKind::Filter)));
             }
         }
-        Ok(false)
+        Ok(None)
     }
 }
 
@@ -1014,26 +1148,26 @@ impl Iterator for Walk {
                     self.ig = self.ig.parent().unwrap();
                 }
                 Ok(WalkEvent::Dir(ent, already_ignored)) => {
-                    let mut ent = DirEntry::new_walkdir(ent, None, already_ignored);
+                    let mut ent = DirEntry::new_walkdir(ent, None);
                     
-                    let should_skip = if already_ignored {
-                        true
+                    let reason = if already_ignored {
+                        self.it.as_ref().unwrap().ignore_everything_deeper_reason.clone()
                     } else {
                         // We should only check the ignore patterns if this entry is not already
                         // the child of an ignored directory
                         match self.skip_entry(&ent) {
                             Err(err) => return Some(Err(err)),
-                            Ok(should_skip) => should_skip,
+                            Ok(reason) => reason.map(Arc::new),
                         }
                     };
 
-                    if should_skip {
-                        ent.ignored = IGNORED;
-
+                    if let Some(reason) = reason {
                         let it = self.it.as_mut().unwrap();
                         if it.ignore_everything_deeper_depth.is_none() {
                             it.ignore_everything_deeper_depth = Some(ent.depth());
+                            it.ignore_everything_deeper_reason = Some(reason.clone());
                         }
+                        ent.set_ignored(reason);
 
                         // Still need to push this on the stack because
                         // we'll get a WalkEvent::Exit event for this dir.
@@ -1048,17 +1182,19 @@ impl Iterator for Walk {
                     return Some(Ok(ent));
                 }
                 Ok(WalkEvent::File(ent, already_ignored)) => {
-                    let mut ent = DirEntry::new_walkdir(ent, None, already_ignored);
+                    let mut ent = DirEntry::new_walkdir(ent, None);
                     if already_ignored {
+                        let it = self.it.as_ref().unwrap();
+                        if let Some(reason) = it.ignore_everything_deeper_reason.clone() {
+                            ent.set_ignored(reason);
+                        }
                         return Some(Ok(ent))
                     };
-                    let should_skip = match self.skip_entry(&ent) {
+                    match self.skip_entry(&ent) {
                         Err(err) => return Some(Err(err)),
-                        Ok(should_skip) => should_skip,
+                        Ok(Some(reason)) => ent.set_ignored(Arc::new(reason)),
+                        Ok(None) => (),
                     };
-                    if should_skip {
-                        ent.ignored = true;
-                    }
                     return Some(Ok(ent));
                 }
             }
@@ -1079,6 +1215,9 @@ struct WalkEventIter {
     // Emitting the ignored files is accomplished by skipping every entry returned by self.it
     // until depth is once again equal to self.ignore_everything_deeper_depth
     ignore_everything_deeper_depth: Option<usize>,
+    // The rule that ignored the directory at ignore_everything_deeper_depth. Every entry below
+    // it reports the same reason.
+    ignore_everything_deeper_reason: Option<Arc<IgnoreReason>>,
 }
 
 #[derive(Debug)]
@@ -1090,7 +1229,13 @@ enum WalkEvent {
 
 impl From<WalkDir> for WalkEventIter {
     fn from(it: WalkDir) -> WalkEventIter {
-        WalkEventIter { depth: 0, it: it.into_iter(), next: None, ignore_everything_deeper_depth: None }
+        WalkEventIter {
+            depth: 0,
+            it: it.into_iter(),
+            next: None,
+            ignore_everything_deeper_depth: None,
+            ignore_everything_deeper_reason: None,
+        }
     }
 }
 
@@ -1113,6 +1258,7 @@ impl Iterator for WalkEventIter {
         let ignored = if let Some(ignore_everything_deeper_depth) = self.ignore_everything_deeper_depth {
             if depth <= ignore_everything_deeper_depth {
                 self.ignore_everything_deeper_depth = None;
+                self.ignore_everything_deeper_reason = None;
                 NOT_IGNORED
             } else {
                 IGNORED
@@ -1296,7 +1442,7 @@ impl WalkParallel {
                     };
                     match DirEntryRaw::from_path(0, path, false) {
                         Ok(dent) => {
-                            (DirEntry::new_raw(dent, None, NOT_IGNORED), root_device)
+                            (DirEntry::new_raw(dent, None), root_device)
                         }
                         Err(err) => {
                             if visitor.visit(Err(err)).is_quit() {
@@ -1355,8 +1501,8 @@ impl WalkParallel {
 /// Message is the set of instructions that a worker knows how to process.
 enum Message {
     /// A work item corresponds to a directory that should be descended into.
-    /// Work items for entries that should be skipped or ignored should not
-    /// be produced.
+    /// Work items for entries that matched an ignore rule are still produced,
+    /// but their directory entry is marked as ignored.
     Work(Work),
     /// This instruction indicates that the worker should quit.
     Quit,
@@ -1421,7 +1567,11 @@ impl Work {
         };
         let (ig, err) = self.ignore.add_child(self.dent.path());
         self.ignore = ig;
-        self.dent.err = err;
+        // Same as the single threaded iterator: nobody cares about a broken
+        // ignore file inside a directory that is already ignored.
+        if !self.dent.ignored {
+            self.dent.err = err;
+        }
         Ok(readdir)
     }
 }
@@ -1580,6 +1730,7 @@ impl<'s> Worker<'s> {
         // entry before passing the error value.
         let readdir = work.read_dir();
         let depth = work.dent.depth();
+        let parent_reason = work.dent.reason.clone();
         let state = self.visitor.visit(Ok(work.dent));
         if !state.is_continue() {
             return state;
@@ -1603,6 +1754,7 @@ impl<'s> Worker<'s> {
                 &work.ignore,
                 depth + 1,
                 work.root_device,
+                parent_reason.as_ref(),
                 result,
             );
             if state.is_quit() {
@@ -1615,21 +1767,24 @@ impl<'s> Worker<'s> {
     /// Decides whether to submit the given directory entry as a file to
     /// search.
     ///
-    /// If the entry is a path that should be ignored, then this is a no-op.
-    /// Otherwise, the entry is pushed on to the queue. (The actual execution
-    /// of the callback happens in `run_one`.)
+    /// The entry is always pushed on to the queue. If the entry is a path
+    /// that should be ignored, then it is marked as ignored first. (The
+    /// actual execution of the callback happens in `run_one`.)
     ///
     /// If an error occurs while reading the entry, then it is sent to the
     /// caller's callback.
     ///
     /// `ig` is the `Ignore` matcher for the parent directory. `depth` should
-    /// be the depth of this entry. `result` should be the item yielded by
-    /// a directory iterator.
+    /// be the depth of this entry. `parent_reason` should be set when the
+    /// parent directory was ignored, in which case no ignore rules are checked
+    /// and the entry is ignored for the same reason. `result` should be the
+    /// item yielded by a directory iterator.
     fn generate_work(
         &mut self,
         ig: &Ignore,
         depth: usize,
         root_device: Option<u64>,
+        parent_reason: Option<&Arc<IgnoreReason>>,
         result: Result<fs::DirEntry, io::Error>,
     ) -> WalkState {
         let fs_dent = match result {
@@ -1641,7 +1796,7 @@ impl<'s> Worker<'s> {
             }
         };
         let mut dent = match DirEntryRaw::from_entry(depth, &fs_dent) {
-            Ok(dent) => DirEntry::new_raw(dent, None, NOT_IGNORED),
+            Ok(dent) => DirEntry::new_raw(dent, None),
             Err(err) => {
                 return self.visitor.visit(Err(err));
             }
@@ -1650,7 +1805,7 @@ impl<'s> Worker<'s> {
         if self.follow_links && is_symlink {
             let path = dent.path().to_path_buf();
             dent = match DirEntryRaw::from_path(depth, path, true) {
-                Ok(dent) => DirEntry::new_raw(dent, None, NOT_IGNORED),
+                Ok(dent) => DirEntry::new_raw(dent, None),
                 Err(err) => {
                     return self.visitor.visit(Err(err));
                 }
@@ -1661,40 +1816,52 @@ impl<'s> Worker<'s> {
                 }
             }
         }
+        // We should only check the ignore patterns if this entry is not
+        // already the child of an ignored directory.
+        let reason = match parent_reason {
+            Some(reason) => Some(reason.clone()),
+            None => match self.skip_entry(ig, &dent) {
+                Ok(reason) => reason.map(Arc::new),
+                Err(err) => return self.visitor.visit(Err(err)),
+            },
+        };
+        if let Some(reason) = reason {
+            dent.set_ignored(reason);
+        }
+        self.send(Work { dent, ignore: ig.clone(), root_device });
+        WalkState::Continue
+    }
+
+    /// Returns the rule, if any, that would have excluded the given entry
+    /// from the walk. This mirrors `Walk::skip_entry`.
+    fn skip_entry(
+        &self,
+        ig: &Ignore,
+        dent: &DirEntry,
+    ) -> Result<Option<IgnoreReason>, Error> {
         // N.B. See analogous call in the single-threaded implementation about
         // why it's important for this to come before the checks below.
-        if should_skip_entry(ig, &dent) {
-            return WalkState::Continue;
+        if let Some(reason) = should_skip_entry(ig, dent) {
+            return Ok(Some(reason));
         }
         if let Some(ref stdout) = self.skip {
-            let is_stdout = match path_equals(&dent, stdout) {
-                Ok(is_stdout) => is_stdout,
-                Err(err) => return self.visitor.visit(Err(err)),
-            };
-            if is_stdout {
-                return WalkState::Continue;
+            if path_equals(dent, stdout)? {
+                return Ok(Some(IgnoreReason::new(IgnoreKind::Stdout)));
             }
         }
-        let should_skip_filesize =
-            if self.max_filesize.is_some() && !dent.is_dir() {
-                skip_filesize(
-                    self.max_filesize.unwrap(),
-                    dent.path(),
-                    &dent.metadata().ok(),
-                )
-            } else {
-                false
-            };
-        let should_skip_filtered =
-            if let Some(Filter(predicate)) = &self.filter {
-                !predicate(&dent)
-            } else {
-                false
-            };
-        if !should_skip_filesize && !should_skip_filtered {
-            self.send(Work { dent, ignore: ig.clone(), root_device });
+        if let Some(max_filesize) = self.max_filesize {
+            if !dent.is_dir()
+                && skip_filesize(max_filesize, dent.path(), &dent.metadata().ok())
+            {
+                return Ok(Some(IgnoreReason::new(IgnoreKind::MaxFilesize)));
+            }
         }
-        WalkState::Continue
+        if let Some(Filter(predicate)) = &self.filter {
+            if !predicate(dent) {
+                return Ok(Some(IgnoreReason::new(IgnoreKind::Filter)));
+            }
+        }
+        Ok(None)
     }
 
     /// Returns the next directory to descend into.
@@ -1833,16 +2000,18 @@ fn skip_filesize(
     }
 }
 
-fn should_skip_entry(ig: &Ignore, dent: &DirEntry) -> bool {
+/// Returns the ignore rule that matched the given entry, if the entry should
+/// be ignored.
+fn should_skip_entry(ig: &Ignore, dent: &DirEntry) -> Option<IgnoreReason> {
     let m = ig.matched_dir_entry(dent);
     if m.is_ignore() {
         log::debug!("ignoring {}: {:?}", dent.path().display(), m);
-        true
+        m.inner().map(|m| m.to_reason())
     } else if m.is_whitelist() {
         log::debug!("whitelisting {}: {:?}", dent.path().display(), m);
-        false
+        None
     } else {
-        false
+        None
     }
 }
 
@@ -1950,6 +2119,7 @@ mod tests {
     use std::sync::{Arc, Mutex};
 
     use super::{DirEntry, WalkBuilder, WalkState};
+    use crate::IgnoreKind;
     use crate::tests::TempDir;
 
     fn wfile<P: AsRef<Path>>(path: P, contents: &str) {
@@ -2010,6 +2180,7 @@ mod tests {
     fn walk_collect_parallel(
         prefix: &Path,
         builder: &WalkBuilder,
+        mode: Mode,
     ) -> Vec<String> {
         let mut paths = vec![];
         for dent in walk_collect_entries_parallel(builder) {
@@ -2017,10 +2188,11 @@ mod tests {
             if path.as_os_str().is_empty() {
                 continue;
             }
-            if dent.ignored {
-                continue
+            match mode {
+                Mode::CollectIgnored if !dent.ignored => continue,
+                Mode::CollectVisible if dent.ignored => continue,
+                _ => paths.push(normal_path(path.to_str().unwrap())),
             }
-            paths.push(normal_path(path.to_str().unwrap()));
         }
         paths.sort();
         paths
@@ -2055,15 +2227,15 @@ mod tests {
     fn assert_paths(prefix: &Path, builder: &WalkBuilder, expected: &[&str]) {
         let got = walk_collect(prefix, builder, Mode::CollectVisible);
         assert_eq!(got, mkpaths(expected), "single threaded");
-        let got = walk_collect_parallel(prefix, builder);
+        let got = walk_collect_parallel(prefix, builder, Mode::CollectVisible);
         assert_eq!(got, mkpaths(expected), "parallel");
     }
 
     fn assert_ignored_paths(prefix: &Path, builder: &WalkBuilder, expected: &[&str]) {
         let got = walk_collect(prefix, builder, Mode::CollectIgnored);
         assert_eq!(got, mkpaths(expected), "single threaded");
-        // NOTE: I did not implement ignored file iteration in parallel. I don't yet have evidence
-        // I need more than a single thread.
+        let got = walk_collect_parallel(prefix, builder, Mode::CollectIgnored);
+        assert_eq!(got, mkpaths(expected), "parallel");
     }
 
     #[test]
@@ -2142,6 +2314,133 @@ mod tests {
         );
     }
 
+    #[test]
+    fn gitignore_descends_into_ignored_dir() {
+        let td = tmpdir();
+        mkdirp(td.path().join(".git"));
+        mkdirp(td.path().join("target/debug/deps"));
+        mkdirp(td.path().join("src"));
+        wfile(td.path().join(".gitignore"), "target/\n");
+        wfile(td.path().join("src/main.rs"), "");
+        wfile(td.path().join("target/debug/deps/libfoo.rlib"), "");
+        // Ignore files inside an ignored directory are not consulted, so this
+        // whitelist has no effect.
+        wfile(td.path().join("target/.gitignore"), "!debug/\n");
+
+        let builder = WalkBuilder::new(td.path());
+        assert_paths(td.path(), &builder, &["src", "src/main.rs"]);
+        assert_ignored_paths(
+            td.path(),
+            &builder,
+            &[
+                ".git",
+                ".gitignore",
+                "target",
+                "target/.gitignore",
+                "target/debug",
+                "target/debug/deps",
+                "target/debug/deps/libfoo.rlib",
+            ],
+        );
+    }
+
+    #[test]
+    fn ignore_reasons() {
+        let td = tmpdir();
+        mkdirp(td.path().join(".git"));
+        mkdirp(td.path().join("target/debug"));
+        mkdirp(td.path().join("a"));
+        let gitignore = td.path().join(".gitignore");
+        wfile(&gitignore, "# build output\n/target\n*.log\n");
+        wfile(td.path().join("target/debug/foo"), "");
+        wfile(td.path().join("a/debug.log"), "");
+        wfile(td.path().join("a/keep.rs"), "");
+
+        let builder = WalkBuilder::new(td.path());
+        let sequential = builder
+            .build()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let parallel = walk_collect_entries_parallel(&builder);
+        for dents in [sequential, parallel] {
+            let reason = |path: &str| {
+                let path = td.path().join(path);
+                let dent = dents.iter().find(|d| d.path() == path).unwrap();
+                assert_eq!(dent.ignored, dent.ignore_reason().is_some());
+                dent.ignore_reason().cloned()
+            };
+            for path in ["target", "target/debug", "target/debug/foo"] {
+                let reason = reason(path).unwrap();
+                assert_eq!(reason.kind(), IgnoreKind::Gitignore);
+                assert_eq!(reason.from(), Some(gitignore.as_path()));
+                assert_eq!(reason.line(), Some(2));
+                assert_eq!(reason.pattern(), Some("/target"));
+            }
+            let log = reason("a/debug.log").unwrap();
+            assert_eq!(log.line(), Some(3));
+            assert_eq!(log.pattern(), Some("*.log"));
+            assert_eq!(reason(".git").unwrap().kind(), IgnoreKind::Hidden);
+            assert_eq!(reason("a/keep.rs"), None);
+            assert_eq!(reason("a"), None);
+        }
+    }
+
+    #[test]
+    fn explain() {
+        let td = tmpdir();
+        mkdirp(td.path().join(".git"));
+        mkdirp(td.path().join("build/keep"));
+        mkdirp(td.path().join("a"));
+        let gitignore = td.path().join(".gitignore");
+        wfile(&gitignore, "!*.log\n/build\n!/build/keep\n*.log\n");
+        wfile(td.path().join("a/.gitignore"), "!debug.log\n");
+        wfile(td.path().join("a/debug.log"), "");
+        wfile(td.path().join("a/other.log"), "");
+        wfile(td.path().join("a/keep.rs"), "");
+        wfile(td.path().join("build/keep/foo"), "");
+
+        let builder = WalkBuilder::new(td.path());
+        let explain = |path: &str| builder.explain(td.path().join(path)).unwrap();
+
+        let ex = explain("a/keep.rs");
+        assert!(!ex.is_ignored());
+        assert!(ex.matches().is_empty());
+
+        // The closer .gitignore wins over the parent one.
+        let ex = explain("a/debug.log");
+        assert!(!ex.is_ignored());
+        assert_eq!(ex.matches().len(), 3);
+        assert!(ex.matches()[0].is_whitelist());
+        assert_eq!(ex.matches()[0].inner().unwrap().pattern(), Some("!debug.log"));
+        assert!(ex.matches()[1].is_ignore());
+        assert_eq!(ex.matches()[1].inner().unwrap().line(), Some(4));
+        assert!(ex.matches()[2].is_whitelist());
+        assert_eq!(ex.matches()[2].inner().unwrap().line(), Some(1));
+
+        // A later line in the same file overrides the whitelist.
+        let ex = explain("a/other.log");
+        assert!(ex.is_ignored());
+        assert_eq!(ex.ignored_parent(), None);
+        assert_eq!(ex.ignore_reason().unwrap().pattern(), Some("*.log"));
+        assert_eq!(ex.matches().len(), 2);
+        assert!(ex.matches()[1].is_whitelist());
+
+        // The whitelist can't re-include anything below an ignored directory.
+        let ex = explain("build/keep/foo");
+        assert!(ex.is_ignored());
+        assert_eq!(ex.ignored_parent(), Some(td.path().join("build").as_path()));
+        let reason = ex.ignore_reason().unwrap();
+        assert_eq!(reason.from(), Some(gitignore.as_path()));
+        assert_eq!(reason.line(), Some(2));
+        let ex = explain("build/keep");
+        assert!(ex.is_ignored());
+        assert!(ex.matches()[0].is_whitelist());
+
+        assert_eq!(explain(".git").ignore_reason().unwrap().kind(), IgnoreKind::Hidden);
+        assert!(builder.explain("/definitely/not/walked").is_err());
+        assert!(builder.explain(td.path().join("a/missing")).is_err());
+    }
+
     #[test]
     fn explicit_ignore() {
         let td = tmpdir();
@@ -2388,5 +2687,12 @@ mod tests {
                 .filter_entry(|entry| entry.file_name() != OsStr::new("a")),
             &["x", "x/y", "x/y/foo"],
         );
+        let mut builder = WalkBuilder::new(td.path());
+        builder.filter_entry(|entry| entry.file_name() != OsStr::new("a"));
+        assert_ignored_paths(
+            td.path(),
+            &builder,
+            &["a", "a/b", "a/b/foo", "a/b/c"],
+        );
     }
 }